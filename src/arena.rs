@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{ldtk::Level, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    components::game_elements::Player,
+    constants::LEVEL_SCALE,
+    resources::{RoundRng, Ruleset, SessionSeed},
+    sudden_death::RoundClock,
+};
+
+/// The IntGrid layer the game reads the arena from.
+pub const COLLISION_LAYER: &str = "CollisionGrid";
+/// Level identifier standing for an arena generated from the session seed, see
+/// [`Arena::generate`].
+pub const GENERATED_LEVEL: &str = "Generated";
+/// Cells of generated arenas are as big as the cells of the LDtk levels.
+const GENERATED_GRID_SIZE: i32 = 16;
+
+/// Up, down, left and right, in grid coordinates.
+pub const DIRECTIONS: [IVec2; 4] = [
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(-1, 0),
+    IVec2::new(1, 0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Floor,
+    /// Breakable block, cleared by explosions
+    Soft,
+    /// Border and pillars
+    Hard,
+}
+
+impl Tile {
+    /// Reads a value of the `CollisionGrid` layer: 1 is breakable, 2 is collidable.
+    pub fn from_int_grid(value: i32) -> Option<Tile> {
+        match value {
+            0 => Some(Tile::Floor),
+            1 => Some(Tile::Soft),
+            2 => Some(Tile::Hard),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Tile {
+        match value {
+            0 => Tile::Floor,
+            1 => Tile::Soft,
+            _ => Tile::Hard,
+        }
+    }
+}
+
+/// Soft block densities the menus cycle through, in percent.
+pub const DENSITIES: [u32; 3] = [40, 70, 90];
+
+/// Size and fill of generated arenas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ArenaLayout {
+    /// Rounded up to odd, so pillars line every side and never stand on a spawn
+    pub width: i32,
+    pub height: i32,
+    /// Chance, in percent, for each free cell to hold a soft block
+    pub density: u32,
+}
+
+impl ArenaLayout {
+    /// The density after this one in [`DENSITIES`].
+    pub fn next_density(&self) -> u32 {
+        let next = DENSITIES
+            .iter()
+            .position(|density| *density == self.density)
+            .map_or(0, |index| (index + 1) % DENSITIES.len());
+        DENSITIES[next]
+    }
+}
+
+impl Default for ArenaLayout {
+    fn default() -> Self {
+        Self {
+            width: 15,
+            height: 13,
+            density: 70,
+        }
+    }
+}
+
+/// The cells of the arena of the current round.
+///
+/// It's part of the rollback state, since explosions clear soft blocks.
+/// Cells are in the same coordinates as [`GridCoords`]: from the bottom-left corner, y up.
+#[derive(Resource, Reflect, Default, Clone, Debug)]
+pub struct Arena {
+    pub width: i32,
+    pub height: i32,
+    /// Size of a cell in LDtk pixels
+    pub grid_size: i32,
+    /// One [`Tile`] per cell, row by row from the bottom
+    tiles: Vec<u8>,
+    /// The [`LevelRevision`] the arena was read at
+    pub revision: u32,
+}
+
+/// Counts the reloads of the LDtk project from disk, so rounds read their level again.
+///
+/// It isn't rollback state, so the reload is pinned to a frame of the [`RoundClock`]: frames
+/// before it keep the old arena however often they're simulated again, and the arena stored with
+/// the rollback state brings it back.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct LevelRevision {
+    pub count: u32,
+    /// The first frame reading the level at this revision
+    pub frame: u32,
+}
+
+impl Arena {
+    /// An arena of floor only.
+    pub fn new(width: i32, height: i32, grid_size: i32) -> Self {
+        Self {
+            width,
+            height,
+            grid_size,
+            tiles: vec![Tile::Floor as u8; (width * height) as usize],
+            revision: 0,
+        }
+    }
+
+    /// Builds the arena from the `CollisionGrid` layer of a level.
+    pub fn from_layer(layer: &LayerInstance) -> Result<Self, String> {
+        let mut arena = Arena::new(layer.c_wid, layer.c_hei, layer.grid_size);
+        if layer.int_grid_csv.len() != arena.tiles.len() {
+            return Err(format!(
+                "layer {} has {} values for {}x{} cells",
+                layer.identifier,
+                layer.int_grid_csv.len(),
+                layer.c_wid,
+                layer.c_hei
+            ));
+        }
+        for (i, value) in layer.int_grid_csv.iter().enumerate() {
+            let tile = Tile::from_int_grid(*value).ok_or_else(|| {
+                format!("unknown IntGrid value {value} in layer {}", layer.identifier)
+            })?;
+            // LDtk lists the rows from the top
+            let (x, row) = (i as i32 % layer.c_wid, i as i32 / layer.c_wid);
+            arena.set_tile(IVec2::new(x, layer.c_hei - 1 - row), tile);
+        }
+        Ok(arena)
+    }
+
+    /// Finds the layer named [`COLLISION_LAYER`] among the layers of `level` and builds the arena
+    /// from it. Fails when the level has no such layer, or when [`Arena::from_layer`] does.
+    pub fn from_level(level: &Level) -> Result<Self, String> {
+        let layer = level
+            .layer_instances
+            .iter()
+            .flatten()
+            .find(|layer| layer.identifier == COLLISION_LAYER)
+            .ok_or_else(|| format!("no {COLLISION_LAYER} layer"))?;
+        Arena::from_layer(layer)
+    }
+
+    /// A classic grid: a hard border, a pillar on every other cell, and soft blocks strewn over
+    /// the rest. The spawns and the cells next to them stay clear, so nobody starts boxed in.
+    ///
+    /// The same seed gives the same arena on every peer.
+    pub fn generate(layout: &ArenaLayout, seed: u64) -> Self {
+        // Pillars stand on even cells, odd sizes keep them off the spawns in the corners
+        let (width, height) = (layout.width.max(5) | 1, layout.height.max(5) | 1);
+        let mut arena = Arena::new(width, height, GENERATED_GRID_SIZE);
+        let mut rng = RoundRng::new(seed);
+        let spawns = arena.spawn_cells();
+        let (right, top) = (arena.width - 1, arena.height - 1);
+
+        for cell in arena.cells().collect::<Vec<_>>() {
+            let border = cell.x == 0 || cell.y == 0 || cell.x == right || cell.y == top;
+            let pillar = cell.x % 2 == 0 && cell.y % 2 == 0;
+            let near_spawn = spawns.iter().any(|spawn| {
+                let distance = (*spawn - cell).abs();
+                distance.x + distance.y <= 1
+            });
+            let tile = if border || pillar {
+                Tile::Hard
+            } else if !near_spawn && rng.chance(layout.density) {
+                Tile::Soft
+            } else {
+                Tile::Floor
+            };
+            arena.set_tile(cell, tile);
+        }
+        arena
+    }
+
+    /// Whether a level was read into the arena yet.
+    pub fn is_built(&self) -> bool {
+        !self.tiles.is_empty()
+    }
+
+    pub fn contains(&self, cell: IVec2) -> bool {
+        (0..self.width).contains(&cell.x) && (0..self.height).contains(&cell.y)
+    }
+
+    /// The tile of a cell, anything outside of the arena is a hard block.
+    pub fn tile(&self, cell: IVec2) -> Tile {
+        if !self.contains(cell) {
+            return Tile::Hard;
+        }
+        Tile::from_u8(self.tiles[self.index(cell)])
+    }
+
+    pub fn set_tile(&mut self, cell: IVec2, tile: Tile) {
+        if self.contains(cell) {
+            let index = self.index(cell);
+            self.tiles[index] = tile as u8;
+        }
+    }
+
+    pub fn is_floor(&self, cell: IVec2) -> bool {
+        self.tile(cell) == Tile::Floor
+    }
+
+    pub fn index(&self, cell: IVec2) -> usize {
+        (cell.y * self.width + cell.x) as usize
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = IVec2> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| IVec2::new(x, y)))
+    }
+
+    /// Size of a cell in world units.
+    pub fn cell_size(&self) -> f32 {
+        self.grid_size as f32 * LEVEL_SCALE
+    }
+
+    /// Center of a cell in world coordinates.
+    pub fn cell_center(&self, cell: IVec2) -> Vec2 {
+        (cell.as_vec2() + Vec2::splat(0.5)) * self.cell_size()
+    }
+
+    /// The cell containing a point of the world.
+    pub fn cell_at(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size()).floor().as_ivec2()
+    }
+
+    /// The four corners inside the border, opposite corners first so two players start far apart.
+    pub fn spawn_cells(&self) -> [IVec2; 4] {
+        let (right, top) = (self.width - 2, self.height - 2);
+        [
+            IVec2::new(1, top),
+            IVec2::new(right, 1),
+            IVec2::new(right, top),
+            IVec2::new(1, 1),
+        ]
+    }
+}
+
+/// Whether the selection stands for a generated arena rather than an LDtk level.
+pub fn is_generated(level_selection: &LevelSelection) -> bool {
+    matches!(
+        level_selection,
+        LevelSelection::Identifier(identifier) if identifier == GENERATED_LEVEL
+    )
+}
+
+/// Reads the selected level into the [`Arena`] once its project is loaded, or generates it from
+/// the session seed, and puts the players on their spawns. A new [`LevelRevision`] reads the
+/// level again.
+///
+/// It runs in the rollback schedule, so every peer builds the arena on the same frame and a
+/// rollback past that frame builds it again.
+pub fn build_arena_system(
+    mut arena: ResMut<Arena>,
+    level_selection: Res<LevelSelection>,
+    projects: Res<Assets<LdtkAsset>>,
+    ruleset: Res<Ruleset>,
+    seed: Res<SessionSeed>,
+    revision: Res<LevelRevision>,
+    clock: Res<RoundClock>,
+    mut player_query: Query<(&mut Transform, &Player)>,
+) {
+    let generated = is_generated(&level_selection);
+    // Generated arenas have no file to reload
+    let reload = arena.revision != revision.count && clock.frames >= revision.frame;
+    if arena.is_built() && (generated || !reload) {
+        return;
+    }
+
+    if generated {
+        *arena = Arena::generate(&ruleset.arena, seed.0);
+    } else {
+        let Some((_, project)) = projects.iter().next() else {
+            return;
+        };
+        let Some(level) = project
+            .project
+            .levels
+            .iter()
+            .enumerate()
+            .find(|(index, level)| level_selection.is_match(index, level))
+            .map(|(_, level)| level)
+        else {
+            return;
+        };
+
+        *arena = match Arena::from_level(level) {
+            Ok(arena) => arena,
+            Err(err) => {
+                error!("Could not read level {}: {err}", level.identifier);
+                // A broken reload keeps the arena it had
+                arena.revision = revision.count;
+                return;
+            }
+        };
+    }
+    arena.revision = revision.count;
+
+    let spawns = arena.spawn_cells();
+    for (mut transform, player) in player_query.iter_mut() {
+        let spawn = arena.cell_center(spawns[player.handle % spawns.len()]);
+        transform.translation.x = spawn.x;
+        transform.translation.y = spawn.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_arenas_are_odd_and_walled_in() {
+        let layout = ArenaLayout {
+            width: 14,
+            height: 12,
+            density: 100,
+        };
+        let arena = Arena::generate(&layout, 1);
+        assert_eq!((arena.width, arena.height), (15, 13));
+        for cell in arena.cells() {
+            let border = cell.x == 0 || cell.y == 0 || cell.x == 14 || cell.y == 12;
+            let pillar = cell.x % 2 == 0 && cell.y % 2 == 0;
+            if border || pillar {
+                assert_eq!(arena.tile(cell), Tile::Hard, "{cell}");
+            } else {
+                assert_ne!(arena.tile(cell), Tile::Hard, "{cell}");
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_start_in_the_clear() {
+        for (width, height) in [(15, 13), (14, 12), (5, 5), (2, 2)] {
+            let layout = ArenaLayout {
+                width,
+                height,
+                density: 100,
+            };
+            let arena = Arena::generate(&layout, 9);
+            for spawn in arena.spawn_cells() {
+                assert!(arena.is_floor(spawn), "{width}x{height}: {spawn}");
+                let exits = DIRECTIONS
+                    .iter()
+                    .filter(|direction| arena.is_floor(spawn + **direction))
+                    .count();
+                assert_eq!(exits, 2, "{width}x{height}: {spawn}");
+            }
+        }
+    }
+
+    #[test]
+    fn density_and_seed_decide_the_soft_blocks() {
+        let soft = |arena: &Arena| arena.cells().filter(|c| arena.tile(*c) == Tile::Soft).count();
+        let layout = ArenaLayout::default();
+        assert_eq!(Arena::generate(&layout, 5).tiles, Arena::generate(&layout, 5).tiles);
+        assert_ne!(Arena::generate(&layout, 5).tiles, Arena::generate(&layout, 6).tiles);
+
+        let empty = ArenaLayout {
+            density: 0,
+            ..layout
+        };
+        assert_eq!(soft(&Arena::generate(&empty, 5)), 0);
+        let full = ArenaLayout {
+            density: 100,
+            ..layout
+        };
+        assert!(soft(&Arena::generate(&full, 5)) > soft(&Arena::generate(&layout, 5)));
+    }
+}
@@ -1,104 +1,753 @@
 use crate::{
+    arena::{Arena, Tile, DIRECTIONS},
     components::{
-        game_elements::{Bomb, BombBag, Explosion, ExplosionTimer, ExplosionToSpawn, FuseTime, Player}, 
-        online::GGRSConfig
+        game_elements::{
+            Airborne, Bomb, BombBag, BombKind, BotControl, Facing, Flame, FuseTime, LandMine,
+            Player, PowerUp, Sliding,
+        },
+        online::{GGRSConfig, RoundEntity},
     },
-    constants::{INPUT_FIRE},
-    resources::GameTextures
+    constants::FPS,
+    input::{player_input, InputAction},
+    resources::{GameTextures, RoundRng},
 };
-use bevy::prelude::*;
-use bevy_ggrs::{ggrs, PlayerInputs};
-use std::time::Duration;
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_ggrs::{PlayerInputs, Rollback, RollbackIdProvider};
+use serde::Deserialize;
 
+/// How long a bomb takes to go off.
+pub const FUSE_FRAMES: u32 = 3 * FPS as u32;
+/// How long the cells of a blast keep burning.
+pub const FLAME_FRAMES: u32 = FPS as u32 / 2;
+/// How far a kicked bomb slides in a frame, in world units.
+pub const KICK_SPEED: f32 = 0.32;
+/// How many cells a thrown bomb flies, see [`BombBag::glove`].
+pub const THROW_CELLS: i32 = 3;
+/// How many cells a punched bomb flies, see [`BombBag::punch`].
+pub const PUNCH_CELLS: i32 = 3;
+/// How far a bomb in the air flies in a frame, in world units.
+pub const THROW_SPEED: f32 = 0.64;
+/// How long a land mine stays in sight before it's armed and hides.
+pub const MINE_ARMING_FRAMES: u32 = FPS as u32;
+
+/// What soft blocks leave behind when they burn.
+///
+/// It changes the simulation, so the handshake makes sure every peer of a session uses the same
+/// table.
+#[derive(Resource, Debug, Clone, Deserialize)]
+pub struct PowerUpTable {
+    /// Chance, in percent, that a soft block leaves a power-up behind
+    pub chance: u32,
+    /// How likely each power-up is compared to the others
+    pub weights: Vec<(PowerUp, u32)>,
+}
+
+impl Default for PowerUpTable {
+    fn default() -> Self {
+        Self {
+            chance: 30,
+            weights: PowerUp::ALL.iter().map(|power_up| (*power_up, 1)).collect(),
+        }
+    }
+}
+
+impl PowerUpTable {
+    /// The power-up left by a burnt soft block, if any.
+    pub fn roll(&self, rng: &mut RoundRng) -> Option<PowerUp> {
+        let total: u32 = self.weights.iter().map(|(_, weight)| weight).sum();
+        if total == 0 || !rng.chance(self.chance) {
+            return None;
+        }
+        let mut pick = rng.below(total);
+        for (power_up, weight) in self.weights.iter() {
+            if pick < *weight {
+                return Some(*power_up);
+            }
+            pick -= weight;
+        }
+        None
+    }
+
+    /// Fixed-width encoding of the table, hashed with the [`Ruleset`] during the handshake.
+    ///
+    /// [`Ruleset`]: crate::resources::Ruleset
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.weights.len() * 5);
+        bytes.extend_from_slice(&self.chance.to_le_bytes());
+        for (power_up, weight) in self.weights.iter() {
+            bytes.push(*power_up as u8);
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// The cells a blast from `origin` reaches: it stops at hard blocks, and soft blocks stop it
+/// after burning unless it `pierce`s them.
+pub fn blast_cells(arena: &Arena, origin: IVec2, range: i32, pierce: bool) -> Vec<IVec2> {
+    let mut cells = vec![origin];
+    for direction in DIRECTIONS {
+        for distance in 1..=range {
+            let cell = origin + direction * distance;
+            match arena.tile(cell) {
+                Tile::Hard => break,
+                Tile::Soft => {
+                    cells.push(cell);
+                    if !pierce {
+                        break;
+                    }
+                }
+                Tile::Floor => cells.push(cell),
+            }
+        }
+    }
+    cells
+}
+
+/// Places a bomb of the [`BombBag`] on the cell of each player pressing [`InputAction::Fire`],
+/// or with the line bomb, every bomb left in a row from there the way they face.
 pub fn player_place_bomb_system(
     mut commands: Commands,
-    inputs: Res<PlayerInputs<GGRSConfig>>,
-    game_texture: Res<GameTextures>,
-    query: Query<(&Transform, &mut BombBag, &Player), With<Player>>,
+    mut rip: ResMut<RollbackIdProvider>,
+    inputs: Option<Res<PlayerInputs<GGRSConfig>>>,
+    arena: Res<Arena>,
+    player_query: Query<(&Transform, &BombBag, &Player, &Facing, Option<&BotControl>)>,
+    bomb_query: Query<(&Transform, &Bomb, &Airborne)>,
 ) {
-    //For each player, check if they pressed the button to place a bomb, and so check on the bomb bag how many they have, so if they have 1, place it
-    for (transform, bomb_bag, player) in query.iter() {
-        let (input, status) = inputs[player.handle];
+    if !arena.is_built() {
+        return;
+    }
+    // Bombs in the air count against the bag but leave their cell free
+    let mut bomb_cells: Vec<(Option<IVec2>, usize)> = bomb_query
+        .iter()
+        .map(|(transform, bomb, airborne)| {
+            let cell = airborne
+                .is_grounded()
+                .then(|| arena.cell_at(transform.translation.xy()));
+            (cell, bomb.owner)
+        })
+        .collect();
 
-        if status != ggrs::InputStatus::Confirmed {
+    let mut players: Vec<_> = player_query.iter().collect();
+    // Rollback ids go out in the same order on every peer, lowest handle first
+    players.sort_by_key(|(_, _, player, ..)| player.handle);
+
+    for (transform, bomb_bag, player, facing, bot) in players {
+        if !player_input(inputs.as_deref(), player.handle, bot).pressed(InputAction::Fire) {
             continue;
         }
-        if input & INPUT_FIRE != 0 {
-            for slot in bomb_bag.slots.iter() {
-                if slot.bomb.is_none() {
-                    //Add a new bomb
-                    commands.spawn((
-                        SpriteBundle {
-                            transform: Transform {
-                                translation: transform.translation,
-                                scale: Vec3::new(0.4, 0.4, 1.0),
-                                ..default()
-                            },
-                            texture: game_texture.player_bomb.clone(),
-                            ..default()
-                        },
-                        Bomb,
-                        FuseTime {
-                            timer: Timer::new(Duration::from_secs(4), TimerMode::Once),
-                        },
-                    ));
-                    //slot.bomb = Some(Bomb);
-                }
+
+        let occupied = |bomb_cells: &[(Option<IVec2>, usize)], cell: IVec2| {
+            bomb_cells.iter().any(|(other, _)| *other == Some(cell))
+        };
+        let mut cell = arena.cell_at(transform.translation.xy());
+        let mut placed = bomb_cells.iter().filter(|(_, owner)| *owner == player.handle).count();
+        // A line starts ahead of the bomb the player stands on
+        if bomb_bag.line && occupied(&bomb_cells, cell) {
+            cell += facing.direction;
+        }
+        // Holding the button doesn't stack bombs on the same cell
+        while placed < bomb_bag.capacity && arena.is_floor(cell) && !occupied(&bomb_cells, cell) {
+            spawn_bomb(&mut commands, &mut rip, arena.cell_center(cell), player, bomb_bag);
+            bomb_cells.push((Some(cell), player.handle));
+            placed += 1;
+            if !bomb_bag.line {
+                break;
             }
+            cell += facing.direction;
         }
     }
 }
 
-//A function which removes the bomb from the bag, and spawns the explosion sprite
+fn spawn_bomb(
+    commands: &mut Commands,
+    rip: &mut RollbackIdProvider,
+    position: Vec2,
+    player: &Player,
+    bomb_bag: &BombBag,
+) {
+    let mut bomb = commands.spawn((
+        SpatialBundle::from_transform(Transform::from_xyz(position.x, position.y, 50.)),
+        Bomb {
+            owner: player.handle,
+            range: bomb_bag.range,
+            kind: bomb_bag.kind,
+        },
+        Sliding::default(),
+        Airborne::default(),
+        Rollback::new(rip.next_id()),
+        RoundEntity,
+    ));
+    // Land mines wait for a player to step on them, remote-controlled bombs for their owner
+    if bomb_bag.kind == BombKind::LandMine {
+        bomb.insert(LandMine {
+            frames: MINE_ARMING_FRAMES,
+        });
+    } else if !bomb_bag.remote {
+        bomb.insert(FuseTime {
+            frames: FUSE_FRAMES,
+        });
+    }
+}
+
+/// Counts the fuses down and blows up the bombs whose fuse ran out, along with every bomb
+/// caught in their blasts. Bombs go off where they are, even halfway through a slide or up in
+/// the air, and sliding into flames sets them off too. Blasts and flames miss bombs in the air.
 pub fn bomb_explosion_system(
-    time: Res<Time>,
     mut commands: Commands,
-    mut query: Query<(Entity, &mut FuseTime)>,
+    mut rip: ResMut<RollbackIdProvider>,
+    mut arena: ResMut<Arena>,
+    mut rng: ResMut<RoundRng>,
+    power_up_table: Res<PowerUpTable>,
+    mut bomb_query: Query<(
+        Entity,
+        &Transform,
+        &Bomb,
+        Option<&mut FuseTime>,
+        &Airborne,
+        &Rollback,
+    )>,
+    flame_query: Query<&Transform, With<Flame>>,
 ) {
-    for (entity, mut fuse_time) in query.iter_mut() {
-        if fuse_time.timer.tick(time.delta()).just_finished() {
-            commands.entity(entity).despawn();
-            //After despawn, should spawn the explosion sprite
-            commands.spawn(ExplosionToSpawn(Vec3::new(50., 50., 0.)));
+    let flame_cells: Vec<IVec2> = flame_query
+        .iter()
+        .map(|transform| arena.cell_at(transform.translation.xy()))
+        .collect();
+    let mut bombs = Vec::new();
+    for (entity, transform, bomb, mut fuse_time, airborne, rollback) in bomb_query.iter_mut() {
+        // Remote-controlled bombs only go off when detonated or caught in a blast
+        let mut burnt_out = false;
+        if let Some(mut fuse_time) = fuse_time {
+            fuse_time.frames = fuse_time.frames.saturating_sub(1);
+            burnt_out = fuse_time.frames == 0;
+        }
+        let cell = arena.cell_at(transform.translation.xy());
+        let grounded = airborne.is_grounded();
+        let lit = burnt_out || grounded && flame_cells.contains(&cell);
+        bombs.push((rollback.id(), entity, cell, *bomb, lit, grounded));
+    }
+    // Query order isn't the same on every peer, the rollback ids are
+    bombs.sort_by_key(|(id, ..)| *id);
+
+    let mut pending: Vec<usize> = (0..bombs.len()).filter(|i| bombs[*i].4).collect();
+    let mut blasts = Vec::new();
+    while let Some(i) = pending.pop() {
+        let (_, entity, cell, bomb, ..) = bombs[i];
+        commands.entity(entity).despawn_recursive();
+        let cells = blast_cells(&arena, cell, bomb.range, bomb.kind == BombKind::Pierce);
+        // Chain reaction
+        for (j, other) in bombs.iter_mut().enumerate() {
+            if !other.4 && other.5 && cells.contains(&other.2) {
+                other.4 = true;
+                pending.push(j);
+            }
         }
+        blasts.push(cells);
+    }
+
+    let mut burning: Vec<IVec2> = Vec::new();
+    for cell in blasts.into_iter().flatten() {
+        if burning.contains(&cell) {
+            continue;
+        }
+        burning.push(cell);
+
+        if arena.tile(cell) == Tile::Soft {
+            arena.set_tile(cell, Tile::Floor);
+            if let Some(power_up) = power_up_table.roll(&mut rng) {
+                let position = arena.cell_center(cell);
+                commands.spawn((
+                    SpatialBundle::from_transform(Transform::from_xyz(position.x, position.y, 40.)),
+                    power_up,
+                    Rollback::new(rip.next_id()),
+                    RoundEntity,
+                ));
+            }
+        }
+
+        let position = arena.cell_center(cell);
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(position.x, position.y, 60.)),
+            Flame {
+                frames: FLAME_FRAMES,
+            },
+            Rollback::new(rip.next_id()),
+            RoundEntity,
+        ));
     }
 }
 
-pub fn explosion_to_spawn_system(
-    mut commands: Commands,
-    game_textures: Res<GameTextures>,
-    query: Query<(Entity, &ExplosionToSpawn)>,
+/// Moves kicked bombs along their lane until the next cell is a block, a bomb or a player,
+/// then rests them on the center of their cell.
+pub fn slide_bombs_system(
+    arena: Res<Arena>,
+    mut bomb_query: Query<
+        (&mut Transform, &mut Sliding, &Airborne, &Rollback),
+        (With<Bomb>, Without<LandMine>),
+    >,
+    player_query: Query<&Transform, (With<Player>, Without<Bomb>)>,
 ) {
-    for (entity, explosion_to_spawn) in query.iter() {
-        commands
-            .spawn(SpriteSheetBundle {
-                texture_atlas: game_textures.explosion.clone(),
-                transform: Transform {
-                    translation: explosion_to_spawn.0,
-                    scale: Vec3::splat(2.0),
-                    ..default()
-                },
-                ..default()
+    let player_cells: Vec<IVec2> = player_query
+        .iter()
+        .map(|transform| arena.cell_at(transform.translation.xy()))
+        .collect();
+    let mut bombs: Vec<_> = bomb_query.iter_mut().collect();
+    // Query order isn't the same on every peer, the rollback ids are
+    bombs.sort_by_key(|(.., rollback)| rollback.id());
+    // Bombs in the air don't stop slides
+    let mut bomb_cells: Vec<Option<IVec2>> = bombs
+        .iter()
+        .map(|(transform, _, airborne, _)| {
+            airborne
+                .is_grounded()
+                .then(|| arena.cell_at(transform.translation.xy()))
+        })
+        .collect();
+
+    for (i, (transform, sliding, airborne, _)) in bombs.iter_mut().enumerate() {
+        if sliding.direction == IVec2::ZERO || !airborne.is_grounded() {
+            continue;
+        }
+        let direction = sliding.direction.as_vec2();
+        let mut position = transform.translation.xy();
+        let cell = arena.cell_at(position);
+        let center = arena.cell_center(cell);
+        let mut step = KICK_SPEED;
+
+        let ahead = cell + sliding.direction;
+        if !arena.is_floor(ahead)
+            || bomb_cells.contains(&Some(ahead))
+            || player_cells.contains(&ahead)
+        {
+            let to_center = (center - position).dot(direction);
+            if to_center <= step {
+                sliding.direction = IVec2::ZERO;
+            }
+            step = step.min(to_center.max(0.));
+        }
+        position += direction * step;
+
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        bomb_cells[i] = Some(arena.cell_at(position));
+    }
+}
+
+/// Players with the power glove lift the bomb on their cell, or the one ahead of them, while
+/// [`InputAction::Grab`] is held, and throw it [`THROW_CELLS`] the way they face on letting go.
+/// Players with the boxing glove punch the bomb ahead of them [`PUNCH_CELLS`] away with
+/// [`InputAction::Punch`].
+/// Bombs carried by players who are gone drop where they are.
+pub fn glove_system(
+    inputs: Option<Res<PlayerInputs<GGRSConfig>>>,
+    arena: Res<Arena>,
+    player_query: Query<(&Transform, &Player, &BombBag, &Facing, Option<&BotControl>)>,
+    mut bomb_query: Query<
+        (&mut Transform, &mut Airborne, &mut Sliding, &Rollback),
+        (With<Bomb>, Without<Player>, Without<LandMine>),
+    >,
+) {
+    if !arena.is_built() {
+        return;
+    }
+    let mut players: Vec<_> = player_query.iter().collect();
+    // Lowest handle first when two players reach for the same bomb
+    players.sort_by_key(|(_, player, ..)| player.handle);
+    let mut bombs: Vec<_> = bomb_query.iter_mut().collect();
+    bombs.sort_by_key(|(.., rollback)| rollback.id());
+
+    for (transform, airborne, ..) in bombs.iter_mut() {
+        if !airborne.carried {
+            continue;
+        }
+        let holder = players
+            .iter()
+            .find(|(_, player, ..)| player.handle == airborne.holder);
+        match holder {
+            Some((holder_transform, ..)) => {
+                transform.translation.x = holder_transform.translation.x;
+                transform.translation.y = holder_transform.translation.y;
+            }
+            None => {
+                let center = arena.cell_center(arena.cell_at(transform.translation.xy()));
+                transform.translation = center.extend(50.);
+                **airborne = Airborne::default();
+            }
+        }
+    }
+
+    for (transform, player, bomb_bag, facing, bot) in players.iter() {
+        let input = player_input(inputs.as_deref(), player.handle, *bot);
+        let grab = input.pressed(InputAction::Grab);
+        let cell = arena.cell_at(transform.translation.xy());
+        let carried = bombs
+            .iter_mut()
+            .find(|(_, airborne, ..)| airborne.carried && airborne.holder == player.handle);
+        if let Some((bomb_transform, airborne, ..)) = carried {
+            if !grab {
+                bomb_transform.translation = arena.cell_center(cell).extend(110.);
+                airborne.carried = false;
+                airborne.direction = facing.direction;
+                airborne.distance = (THROW_CELLS as f32) * arena.cell_size();
+            }
+            continue;
+        }
+        let punch = input.pressed(InputAction::Punch);
+        if !grab && !punch {
+            continue;
+        }
+
+        let ahead = cell + facing.direction;
+        let grounded_at = |bomb_cell: IVec2| {
+            bombs.iter().position(|(bomb_transform, airborne, ..)| {
+                airborne.is_grounded()
+                    && arena.cell_at(bomb_transform.translation.xy()) == bomb_cell
             })
-            .insert(Explosion)
-            .insert(ExplosionTimer::default());
+        };
+        let (lift, punch) = match (grounded_at(cell), grounded_at(ahead)) {
+            (_, Some(i)) if punch && bomb_bag.punch => (None, Some(i)),
+            (Some(i), _) if grab && bomb_bag.glove => (Some(i), None),
+            (_, Some(i)) if grab && bomb_bag.glove => (Some(i), None),
+            _ => (None, None),
+        };
+        if let Some(i) = lift {
+            let (bomb_transform, airborne, sliding, _) = &mut bombs[i];
+            bomb_transform.translation.z = 110.;
+            sliding.direction = IVec2::ZERO;
+            **airborne = Airborne {
+                carried: true,
+                holder: player.handle,
+                ..default()
+            };
+        }
+        if let Some(i) = punch {
+            let (bomb_transform, airborne, sliding, _) = &mut bombs[i];
+            bomb_transform.translation = arena.cell_center(ahead).extend(110.);
+            sliding.direction = IVec2::ZERO;
+            **airborne = Airborne {
+                direction: facing.direction,
+                distance: (PUNCH_CELLS as f32) * arena.cell_size(),
+                ..default()
+            };
+        }
+    }
+}
+
+/// Flies thrown and punched bombs over whatever is in the way, wrapping around the edges of the
+/// arena. Once they've flown far enough they land on the center of their cell, or bounce one
+/// cell further while it's a block, a bomb or a player.
+pub fn fly_bombs_system(
+    arena: Res<Arena>,
+    mut bomb_query: Query<
+        (&mut Transform, &mut Airborne, &Rollback),
+        (With<Bomb>, Without<LandMine>),
+    >,
+    player_query: Query<&Transform, (With<Player>, Without<Bomb>)>,
+) {
+    let player_cells: Vec<IVec2> = player_query
+        .iter()
+        .map(|transform| arena.cell_at(transform.translation.xy()))
+        .collect();
+    let mut bombs: Vec<_> = bomb_query.iter_mut().collect();
+    // Query order isn't the same on every peer, the rollback ids are
+    bombs.sort_by_key(|(.., rollback)| rollback.id());
+    let mut bomb_cells: Vec<Option<IVec2>> = bombs
+        .iter()
+        .map(|(transform, airborne, _)| {
+            airborne
+                .is_grounded()
+                .then(|| arena.cell_at(transform.translation.xy()))
+        })
+        .collect();
+
+    let size = Vec2::new(arena.width as f32, arena.height as f32) * arena.cell_size();
+    for (i, (transform, airborne, _)) in bombs.iter_mut().enumerate() {
+        if airborne.carried || airborne.direction == IVec2::ZERO {
+            continue;
+        }
+        let step = THROW_SPEED.min(airborne.distance);
+        let position = transform.translation.xy() + airborne.direction.as_vec2() * step;
+        let position = Vec2::new(position.x.rem_euclid(size.x), position.y.rem_euclid(size.y));
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        airborne.distance -= step;
+        if airborne.distance > 0. {
+            continue;
+        }
+
+        let cell = arena.cell_at(position);
+        if !arena.is_floor(cell)
+            || bomb_cells.contains(&Some(cell))
+            || player_cells.contains(&cell)
+        {
+            airborne.distance = arena.cell_size();
+            continue;
+        }
+        transform.translation = arena.cell_center(cell).extend(50.);
+        **airborne = Airborne::default();
+        bomb_cells[i] = Some(cell);
+    }
+}
+
+/// Sets off the oldest remote-controlled bomb of each player pressing [`InputAction::Detonate`],
+/// by giving it a fuse of a single frame. Remote-controlled bombs of players who lost the remote
+/// or are gone get a full fuse.
+pub fn remote_control_system(
+    mut commands: Commands,
+    inputs: Option<Res<PlayerInputs<GGRSConfig>>>,
+    mut player_query: Query<(&Player, &mut BombBag, Option<&BotControl>)>,
+    bomb_query: Query<(Entity, &Bomb, &Rollback), (Without<FuseTime>, Without<LandMine>)>,
+) {
+    let mut bombs: Vec<_> = bomb_query.iter().collect();
+    // Rollback ids count up, the oldest bomb has the lowest
+    bombs.sort_by_key(|(.., rollback)| rollback.id());
+
+    let mut remotes = Vec::new();
+    for (player, mut bomb_bag, bot) in player_query.iter_mut() {
+        if !bomb_bag.remote {
+            continue;
+        }
+        remotes.push(player.handle);
+        let detonate = player_input(inputs.as_deref(), player.handle, bot)
+            .pressed(InputAction::Detonate);
+        let held = bomb_bag.detonating;
+        if held != detonate {
+            bomb_bag.detonating = detonate;
+        }
+        if !detonate || held {
+            continue;
+        }
+        if let Some((entity, ..)) = bombs.iter().find(|(_, bomb, _)| bomb.owner == player.handle) {
+            commands.entity(*entity).insert(FuseTime { frames: 1 });
+        }
+    }
+
+    for (entity, bomb, _) in bombs.iter() {
+        if !remotes.contains(&bomb.owner) {
+            commands.entity(*entity).insert(FuseTime {
+                frames: FUSE_FRAMES,
+            });
+        }
+    }
+}
+
+/// Counts down to arming the land mines, then sets the armed ones a player stands on off with a
+/// fuse of a single frame.
+pub fn land_mine_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut mine_query: Query<(Entity, &Transform, &mut LandMine), Without<FuseTime>>,
+    player_query: Query<&Transform, (With<Player>, Without<LandMine>)>,
+) {
+    let player_cells: Vec<IVec2> = player_query
+        .iter()
+        .map(|transform| arena.cell_at(transform.translation.xy()))
+        .collect();
+    for (entity, transform, mut mine) in mine_query.iter_mut() {
+        if !mine.is_armed() {
+            mine.frames -= 1;
+        } else if player_cells.contains(&arena.cell_at(transform.translation.xy())) {
+            commands.entity(entity).insert(FuseTime { frames: 1 });
+        }
+    }
+}
+
+pub fn flame_system(mut commands: Commands, mut flame_query: Query<(Entity, &mut Flame)>) {
+    for (entity, mut flame) in flame_query.iter_mut() {
+        flame.frames = flame.frames.saturating_sub(1);
+        if flame.frames == 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
 
-        commands.entity(entity).despawn();
+/// Players die on burning cells. Cells rather than sensor colliders, so rollbacks replay it
+/// the same on every peer.
+pub fn kill_players_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    flame_query: Query<&Transform, With<Flame>>,
+) {
+    let burning: Vec<IVec2> = flame_query
+        .iter()
+        .map(|transform| arena.cell_at(transform.translation.xy()))
+        .collect();
+
+    for (entity, transform) in player_query.iter() {
+        if burning.contains(&arena.cell_at(transform.translation.xy())) {
+            commands.entity(entity).despawn_recursive();
+        }
     }
 }
 
-pub fn explosion_animation_system(
+/// Players take the power-ups on their cell.
+pub fn pick_up_power_ups_system(
     mut commands: Commands,
-    time: Res<Time>,
-    mut query: Query<(Entity, &mut TextureAtlasSprite, &mut ExplosionTimer), With<Explosion>>,
+    arena: Res<Arena>,
+    mut player_query: Query<(&Transform, &mut BombBag, &Player)>,
+    power_up_query: Query<(Entity, &Transform, &PowerUp)>,
 ) {
-    for (entity, mut sprite, mut timer) in query.iter_mut() {
-        timer.0.tick(time.delta());
-        if timer.0.finished() {
-            sprite.index += 1;
-            if sprite.index >= 16 {
-                commands.entity(entity).despawn();
+    // Lowest handle first when two players step on the same power-up
+    let mut players: Vec<_> = player_query.iter_mut().collect();
+    players.sort_by_key(|(.., player)| player.handle);
+
+    let mut taken = Vec::new();
+    for (player_transform, bomb_bag, _) in players.iter_mut() {
+        let cell = arena.cell_at(player_transform.translation.xy());
+        for (entity, transform, power_up) in power_up_query.iter() {
+            if taken.contains(&entity) || arena.cell_at(transform.translation.xy()) != cell {
+                continue;
+            }
+            match power_up {
+                PowerUp::ExtraBomb => bomb_bag.capacity += 1,
+                PowerUp::BlastRange => bomb_bag.range += 1,
+                PowerUp::Kick => bomb_bag.kick = true,
+                PowerUp::Glove => bomb_bag.glove = true,
+                PowerUp::BoxingGlove => bomb_bag.punch = true,
+                PowerUp::Remote => bomb_bag.remote = true,
+                PowerUp::Pierce => bomb_bag.kind = BombKind::Pierce,
+                PowerUp::LineBomb => bomb_bag.line = true,
+                PowerUp::LandMine => bomb_bag.kind = BombKind::LandMine,
             }
+            taken.push(entity);
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
+
+/// Bombs, flames and power-ups are spawned by the rollback systems without sprites, since a
+/// rollback can respawn them with their registered components only. The sprites are added here.
+pub fn add_sprites_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    game_textures: Res<GameTextures>,
+    bomb_query: Query<(Entity, &Bomb), Without<Sprite>>,
+    flame_query: Query<Entity, (With<Flame>, Without<TextureAtlasSprite>)>,
+    power_up_query: Query<(Entity, &PowerUp), Without<Sprite>>,
+) {
+    let size = Some(Vec2::splat(arena.cell_size()));
+
+    for (entity, bomb) in bomb_query.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: size,
+                ..default()
+            },
+            game_textures.bomb(bomb.kind),
+        ));
+    }
+    for entity in flame_query.iter() {
+        commands.entity(entity).insert((
+            TextureAtlasSprite {
+                custom_size: size,
+                ..default()
+            },
+            game_textures.explosion.clone(),
+        ));
+    }
+    for (entity, power_up) in power_up_query.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                color: power_up.color(),
+                custom_size: size.map(|size| size * 0.6),
+                ..default()
+            },
+            Handle::<Image>::default(),
+        ));
+    }
+}
+
+/// Hides land mines once they're armed, and shows them again if a rollback disarms them.
+/// Mines a rollback respawned are checked once they can be drawn again.
+pub fn hide_land_mines_system(
+    mut mine_query: Query<(&LandMine, &mut Visibility), Or<(Changed<LandMine>, Added<Visibility>)>>,
+) {
+    for (mine, mut visibility) in mine_query.iter_mut() {
+        visibility.is_visible = !mine.is_armed();
+    }
+}
+
+/// Plays the 16 frames of the explosion sheet over the life of a flame.
+pub fn explosion_animation_system(mut query: Query<(&mut TextureAtlasSprite, &Flame)>) {
+    for (mut sprite, flame) in query.iter_mut() {
+        let burnt = FLAME_FRAMES.saturating_sub(flame.frames);
+        sprite.index = (burnt * 16 / FLAME_FRAMES).min(15) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cells: Vec<IVec2>) -> Vec<(i32, i32)> {
+        cells.sort_by_key(|cell| (cell.x, cell.y));
+        cells.into_iter().map(|cell| (cell.x, cell.y)).collect()
+    }
+
+    /// Floor everywhere but a hard block above the middle and soft blocks right of it.
+    fn arena() -> Arena {
+        let mut arena = Arena::new(7, 7, 16);
+        arena.set_tile(IVec2::new(3, 5), Tile::Hard);
+        arena.set_tile(IVec2::new(4, 3), Tile::Soft);
+        arena.set_tile(IVec2::new(5, 3), Tile::Soft);
+        arena
+    }
+
+    #[test]
+    fn blasts_stop_at_blocks() {
+        let cells = blast_cells(&arena(), IVec2::new(3, 3), 2, false);
+        assert_eq!(
+            sorted(cells),
+            vec![(1, 3), (2, 3), (3, 1), (3, 2), (3, 3), (3, 4), (4, 3)]
+        );
+    }
+
+    #[test]
+    fn pierce_blasts_go_through_soft_blocks() {
+        let cells = blast_cells(&arena(), IVec2::new(3, 3), 3, true);
+        assert!(cells.contains(&IVec2::new(5, 3)));
+        assert!(cells.contains(&IVec2::new(6, 3)));
+        assert!(!cells.contains(&IVec2::new(3, 5)));
+    }
+
+    #[test]
+    fn blasts_stay_inside_the_arena() {
+        let cells = blast_cells(&Arena::new(3, 3, 16), IVec2::new(0, 0), 5, true);
+        assert!(cells.iter().all(|cell| (0..3).contains(&cell.x) && (0..3).contains(&cell.y)));
+        assert_eq!(cells.len(), 5);
+    }
+
+    #[test]
+    fn power_ups_follow_the_table() {
+        let mut rng = RoundRng::new(7);
+        let never = PowerUpTable {
+            chance: 0,
+            ..default()
+        };
+        assert!((0..100).all(|_| never.roll(&mut rng).is_none()));
+
+        let only_kicks = PowerUpTable {
+            chance: 100,
+            weights: vec![(PowerUp::ExtraBomb, 0), (PowerUp::Kick, 3)],
+        };
+        assert!((0..100).all(|_| only_kicks.roll(&mut rng) == Some(PowerUp::Kick)));
+
+        let empty = PowerUpTable {
+            chance: 100,
+            weights: Vec::new(),
+        };
+        assert_eq!(empty.roll(&mut rng), None);
+    }
+
+    #[test]
+    fn same_seed_same_power_ups() {
+        let table = PowerUpTable::default();
+        let rolls = |seed| {
+            let mut rng = RoundRng::new(seed);
+            (0..50).map(|_| table.roll(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(3), rolls(3));
+        assert!(rolls(3).iter().any(Option::is_some));
+    }
+}
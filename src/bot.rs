@@ -0,0 +1,485 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::{
+    asset::{AssetLoader, Error, LoadContext, LoadedAsset},
+    math::Vec3Swizzles,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_ggrs::{
+    ggrs::{InputStatus, PlayerHandle},
+    PlayerInputs,
+};
+use serde::Deserialize;
+
+use crate::{
+    arena::{Arena, Tile, DIRECTIONS},
+    bomb::{blast_cells, FLAME_FRAMES, FUSE_FRAMES},
+    components::{
+        game_elements::{
+            Bomb, BombBag, BombKind, BotControl, Flame, FuseTime, LandMine, Player, PowerUp,
+        },
+        online::GGRSConfig,
+    },
+    input::{InputAction, PlayerInput},
+    player::MOVE_SPEED,
+    resources::SessionSeed,
+};
+
+/// How a bot plays, tuned in `assets/bots/profiles.bots.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BotProfile {
+    pub name: String,
+    /// Frames between the bot seeing something and its input reacting to it
+    pub reaction_frames: usize,
+    /// How many cells away the bot looks for targets and ways out
+    pub look_ahead: u32,
+    /// Chance, in percent, to place a bomb without a way out of it
+    pub mistake_chance: u32,
+    /// Chance, in percent, to go after players rather than soft blocks after each bomb
+    pub aggression: u32,
+}
+
+impl Default for BotProfile {
+    fn default() -> Self {
+        Self {
+            name: "Normal".to_owned(),
+            reaction_frames: 8,
+            look_ahead: 12,
+            mistake_chance: 2,
+            aggression: 40,
+        }
+    }
+}
+
+/// The difficulty tiers offered for bots, in the order the menu cycles through them.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "5a0b6c1e-3f57-4c2e-9d6a-8f1e2b7c4d90"]
+pub struct BotProfiles {
+    pub profiles: Vec<BotProfile>,
+}
+
+impl BotProfiles {
+    /// The profile at `index`, or the default one if the asset has fewer.
+    pub fn get(&self, index: usize) -> BotProfile {
+        self.profiles.get(index).cloned().unwrap_or_default()
+    }
+}
+
+/// Loads `.bots.ron` files into [`BotProfiles`].
+#[derive(Default)]
+pub struct BotProfilesLoader;
+
+impl AssetLoader for BotProfilesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let profiles: BotProfiles = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(profiles));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bots.ron"]
+    }
+}
+
+/// Bot seats of the current round, and how their bots play.
+///
+/// Bots aren't GGRS players: every peer runs them inside the rollback schedule, see
+/// [`bot_think_system`]. Their handles come after the GGRS ones.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct BotPlayers {
+    pub handles: Vec<PlayerHandle>,
+    /// Part of the simulation, so it has to be the same on every peer
+    pub profile: BotProfile,
+}
+
+/// A living player, as a bot sees it.
+#[derive(Debug, Clone)]
+pub struct BotPlayer {
+    pub handle: PlayerHandle,
+    pub position: Vec2,
+    pub bomb_bag: BombBag,
+}
+
+/// A bomb on the field, as a bot sees it.
+#[derive(Debug, Clone, Copy)]
+pub struct BotBomb {
+    pub cell: IVec2,
+    pub bomb: Bomb,
+    pub fuse: u32,
+    /// Goes off when its owner detonates it, see [`BombBag::remote`]
+    pub remote: bool,
+}
+
+/// Everything a bot decides from: a snapshot of the round.
+pub struct BotView<'a> {
+    pub arena: &'a Arena,
+    pub players: Vec<BotPlayer>,
+    pub bombs: Vec<BotBomb>,
+    /// Burning cells and the frames left until they go out
+    pub flames: Vec<(IVec2, u32)>,
+    pub power_ups: Vec<IVec2>,
+}
+
+impl<'a> BotView<'a> {
+    fn bomb_at(&self, cell: IVec2) -> bool {
+        self.bombs.iter().any(|bomb| bomb.cell == cell)
+    }
+
+    /// Frames a player needs to walk from one cell to the next.
+    fn frames_per_cell(&self) -> u32 {
+        (self.arena.cell_size() / MOVE_SPEED).ceil() as u32
+    }
+}
+
+/// When each cell will burn, from the bombs on the field and the flames already burning.
+pub struct DangerMap {
+    width: i32,
+    /// First frame and last frame each cell burns, if anything will burn it
+    burning: Vec<Option<(u32, u32)>>,
+}
+
+impl DangerMap {
+    pub fn new(arena: &Arena, bombs: &[BotBomb], flames: &[(IVec2, u32)]) -> Self {
+        let mut map = Self {
+            width: arena.width,
+            burning: vec![None; (arena.width * arena.height) as usize],
+        };
+
+        // A bomb caught in another blast goes off with it
+        let blasts: Vec<Vec<IVec2>> = bombs
+            .iter()
+            .map(|bomb| {
+                let pierce = bomb.bomb.kind == BombKind::Pierce;
+                blast_cells(arena, bomb.cell, bomb.bomb.range, pierce)
+            })
+            .collect();
+        let mut fuses: Vec<u32> = bombs.iter().map(|bomb| bomb.fuse).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, blast) in blasts.iter().enumerate() {
+                for (j, other) in bombs.iter().enumerate() {
+                    if fuses[i] < fuses[j] && blast.contains(&other.cell) {
+                        fuses[j] = fuses[i];
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        for (blast, fuse) in blasts.iter().zip(fuses) {
+            for cell in blast {
+                map.add(arena, *cell, fuse, fuse + FLAME_FRAMES);
+            }
+        }
+        for (cell, frames) in flames {
+            map.add(arena, *cell, 0, *frames);
+        }
+        map
+    }
+
+    fn add(&mut self, arena: &Arena, cell: IVec2, from: u32, to: u32) {
+        if !arena.contains(cell) {
+            return;
+        }
+        let index = (cell.y * self.width + cell.x) as usize;
+        self.burning[index] = Some(match self.burning[index] {
+            Some((start, end)) => (start.min(from), end.max(to)),
+            None => (from, to),
+        });
+    }
+
+    fn burning(&self, cell: IVec2) -> Option<(u32, u32)> {
+        self.burning
+            .get((cell.y * self.width + cell.x) as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Whether nothing on the field will ever burn the cell.
+    pub fn is_safe(&self, cell: IVec2) -> bool {
+        self.burning(cell).is_none()
+    }
+
+    /// Whether the cell burns at some point between the two frames.
+    pub fn burns_between(&self, cell: IVec2, from: u32, to: u32) -> bool {
+        self.burning(cell)
+            .map_or(false, |(start, end)| start <= to && from <= end)
+    }
+}
+
+/// The first step towards the closest cell matching `goal` that can be walked to without
+/// getting burnt on the way, with the cell and the frames needed to get there.
+/// Cells more than `max_cells` steps away aren't considered.
+fn search(
+    view: &BotView,
+    danger: &DangerMap,
+    start: IVec2,
+    max_cells: u32,
+    goal: impl Fn(IVec2) -> bool,
+) -> Option<(IVec2, IVec2, u32)> {
+    let step = view.frames_per_cell();
+    let mut first_steps: HashMap<IVec2, IVec2> = HashMap::from([(start, start)]);
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((cell, frames)) = queue.pop_front() {
+        if goal(cell) {
+            return Some((first_steps[&cell], cell, frames));
+        }
+        if frames / step >= max_cells {
+            continue;
+        }
+        for direction in DIRECTIONS {
+            let next = cell + direction;
+            let arrival = frames + step;
+            if first_steps.contains_key(&next)
+                || !view.arena.is_floor(next)
+                || view.bomb_at(next)
+                // it has to stay unburnt from entering the cell until leaving it
+                || danger.burns_between(next, frames, arrival + step)
+            {
+                continue;
+            }
+            let first_step = if cell == start { next } else { first_steps[&cell] };
+            first_steps.insert(next, first_step);
+            queue.push_back((next, arrival));
+        }
+    }
+    None
+}
+
+/// Presses the direction leading from `position` to the center of `target`, lining up with the
+/// grid first so the player doesn't clip the corners of pillars.
+fn steer(arena: &Arena, position: Vec2, target: IVec2) -> PlayerInput {
+    let mut input = PlayerInput::default();
+    let current = arena.cell_center(arena.cell_at(position));
+    let target = arena.cell_center(target);
+    let offset = target - position;
+
+    let (along_x, line_up) = if (target.x - current.x).abs() > f32::EPSILON {
+        (true, current.y - position.y)
+    } else if (target.y - current.y).abs() > f32::EPSILON {
+        (false, current.x - position.x)
+    } else {
+        // Staying on the current cell, just center on it
+        (offset.x.abs() > offset.y.abs(), 0.)
+    };
+
+    let action = if line_up.abs() > MOVE_SPEED {
+        match (along_x, line_up > 0.) {
+            (true, true) => InputAction::Up,
+            (true, false) => InputAction::Down,
+            (false, true) => InputAction::Right,
+            (false, false) => InputAction::Left,
+        }
+    } else if along_x && offset.x.abs() > MOVE_SPEED {
+        if offset.x > 0. { InputAction::Right } else { InputAction::Left }
+    } else if !along_x && offset.y.abs() > MOVE_SPEED {
+        if offset.y > 0. { InputAction::Up } else { InputAction::Down }
+    } else {
+        return input;
+    };
+    input.press(action);
+    input
+}
+
+/// Picks the input of the bot playing `handle`.
+///
+/// In order: get out of blast lines, detonate remote-controlled bombs, place a bomb if it hits
+/// a soft block or another player and there's a way out of its blast, walk to the closest
+/// power-up, then walk to the closest spot worth bombing, players first while hunting.
+fn think(
+    view: &BotView,
+    handle: PlayerHandle,
+    profile: &BotProfile,
+    brain: &mut BotControl,
+) -> PlayerInput {
+    let Some(me) = view.players.iter().find(|player| player.handle == handle) else {
+        return PlayerInput::default();
+    };
+    let arena = view.arena;
+    let cell = arena.cell_at(me.position);
+    let look_ahead = profile.look_ahead;
+    let danger = DangerMap::new(arena, &view.bombs, &view.flames);
+
+    if !danger.is_safe(cell) {
+        return match search(view, &danger, cell, look_ahead, |cell| danger.is_safe(cell)) {
+            Some((step, ..)) => steer(arena, me.position, step),
+            // Nowhere to go, better not walk into the flames
+            None => PlayerInput::default(),
+        };
+    }
+
+    // Out of every blast, set the oldest remote-controlled bomb off, letting go in between
+    let last = brain.delayed.last().copied().unwrap_or(brain.input);
+    let remote = view
+        .bombs
+        .iter()
+        .any(|bomb| bomb.remote && bomb.bomb.owner == handle);
+    if remote && !last.pressed(InputAction::Detonate) {
+        let mut input = PlayerInput::default();
+        input.press(InputAction::Detonate);
+        return input;
+    }
+
+    let pierce = me.bomb_bag.kind == BombKind::Pierce;
+    let blast = |cell: IVec2| blast_cells(arena, cell, me.bomb_bag.range, pierce);
+    let hits_block = |cell: IVec2| {
+        blast(cell)
+            .iter()
+            .any(|blasted| arena.tile(*blasted) == Tile::Soft)
+    };
+    let hits_player = |cell: IVec2| {
+        let blasted = blast(cell);
+        view.players.iter().any(|other| {
+            other.handle != handle && blasted.contains(&arena.cell_at(other.position))
+        })
+    };
+
+    let placed = view.bombs.iter().filter(|bomb| bomb.bomb.owner == handle).count();
+    if placed < me.bomb_bag.capacity
+        && !view.bomb_at(cell)
+        && (hits_block(cell) || hits_player(cell))
+    {
+        // Only bomb with a way out of the new blast, unless the bot slips up
+        let mut bombs = view.bombs.clone();
+        bombs.push(BotBomb {
+            cell,
+            bomb: Bomb {
+                owner: handle,
+                range: me.bomb_bag.range,
+                kind: me.bomb_bag.kind,
+            },
+            fuse: FUSE_FRAMES,
+            remote: me.bomb_bag.remote && me.bomb_bag.kind != BombKind::LandMine,
+        });
+        let danger = DangerMap::new(arena, &bombs, &view.flames);
+        let way_out = search(view, &danger, cell, look_ahead, |cell| danger.is_safe(cell));
+        if way_out.is_some() || brain.rng.chance(profile.mistake_chance) {
+            brain.hunting = brain.rng.chance(profile.aggression);
+            let mut input = PlayerInput::default();
+            input.press(InputAction::Fire);
+            return input;
+        }
+    }
+
+    let find = |goal: &dyn Fn(IVec2) -> bool| {
+        search(view, &danger, cell, look_ahead, |target| {
+            target != cell && danger.is_safe(target) && goal(target)
+        })
+    };
+    let power_up = find(&|target| view.power_ups.contains(&target));
+    let target = if brain.hunting {
+        power_up
+            .or_else(|| find(&hits_player))
+            .or_else(|| find(&hits_block))
+    } else {
+        power_up
+            .or_else(|| find(&hits_block))
+            .or_else(|| find(&hits_player))
+    };
+    match target {
+        Some((step, ..)) => steer(arena, me.position, step),
+        None => steer(arena, me.position, cell),
+    }
+}
+
+/// Lets every bot pick its input for the frame, from the rollback state only.
+///
+/// The decisions only depend on registered components and resources, the bot profile and the
+/// session seed, so every peer computes the same bot inputs, and re-simulating a frame after a
+/// rollback makes the same decisions again without sending anything.
+pub fn bot_think_system(
+    bots: Option<Res<BotPlayers>>,
+    arena: Res<Arena>,
+    mut player_query: Query<(&Transform, &Player, &BombBag, Option<&mut BotControl>)>,
+    bomb_query: Query<(&Transform, &Bomb, Option<&FuseTime>, Option<&LandMine>)>,
+    flame_query: Query<(&Transform, &Flame)>,
+    power_up_query: Query<&Transform, With<PowerUp>>,
+) {
+    if !arena.is_built() {
+        return;
+    }
+    let profile = bots.map_or_else(BotProfile::default, |bots| bots.profile.clone());
+
+    let cell_of = |transform: &Transform| arena.cell_at(transform.translation.xy());
+    let mut view = BotView {
+        arena: &arena,
+        players: player_query
+            .iter()
+            .map(|(transform, player, bomb_bag, _)| BotPlayer {
+                handle: player.handle,
+                position: transform.translation.xy(),
+                bomb_bag: bomb_bag.clone(),
+            })
+            .collect(),
+        bombs: bomb_query
+            .iter()
+            // Remote-controlled bombs and land mines could go off any time, a full fuse is a
+            // fair guess
+            .map(|(transform, bomb, fuse_time, mine)| BotBomb {
+                cell: cell_of(transform),
+                bomb: *bomb,
+                fuse: fuse_time.map_or(FUSE_FRAMES, |fuse_time| fuse_time.frames),
+                remote: fuse_time.is_none() && mine.is_none(),
+            })
+            .collect(),
+        flames: flame_query
+            .iter()
+            .map(|(transform, flame)| (cell_of(transform), flame.frames))
+            .collect(),
+        power_ups: power_up_query.iter().map(cell_of).collect(),
+    };
+    // Ties between equally good targets go the same way on every peer, whatever the query order
+    let key = |cell: &IVec2| (cell.y, cell.x);
+    view.players.sort_by_key(|player| player.handle);
+    view.bombs.sort_by_key(|bomb| key(&bomb.cell));
+    view.flames.sort_by_key(|(cell, _)| key(cell));
+    view.power_ups.sort_by_key(key);
+
+    // Each bot decides from the same view, so the order they're visited in doesn't matter
+    for (_, player, _, brain) in player_query.iter_mut() {
+        let Some(mut brain) = brain else {
+            continue;
+        };
+        let decision = think(&view, player.handle, &profile, &mut brain);
+
+        // The input decided now is pressed a reaction time later
+        brain.delayed.push(decision);
+        brain.input = if brain.delayed.len() > profile.reaction_frames {
+            brain.delayed.remove(0)
+        } else {
+            PlayerInput::default()
+        };
+    }
+}
+
+/// Hands the player of a peer that dropped out over to a bot.
+///
+/// GGRS reports the disconnect on the same frame for every peer, so they all start running the
+/// bot from that frame on.
+pub fn take_over_disconnected_system(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<GGRSConfig>>,
+    seed: Res<SessionSeed>,
+    player_query: Query<(Entity, &Player), Without<BotControl>>,
+) {
+    for (entity, player) in player_query.iter() {
+        let disconnected = inputs
+            .get(player.handle)
+            .map_or(false, |(_, status)| *status == InputStatus::Disconnected);
+        if disconnected {
+            commands
+                .entity(entity)
+                .insert(BotControl::new(seed.0, player.handle));
+        }
+    }
+}
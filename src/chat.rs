@@ -0,0 +1,347 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_ggrs::ggrs::PlayerType;
+
+use crate::{
+    network::{MatchSocket, PeerPacket, PeerSocket},
+    resources::FontAssets,
+};
+
+const CHAT_MAGIC: &[u8; 4] = b"BBCH";
+const MAX_MESSAGE_LEN: usize = 80;
+const MAX_LOG_LINES: usize = 8;
+const LINE_LIFETIME_SECS: f32 = 8.;
+const LINE_FADE_SECS: f32 = 2.;
+
+/// Opens the chat box.
+pub const CHAT_KEY: KeyCode = KeyCode::T;
+/// Quick emotes, sent with the number keys while the chat box is closed.
+pub const EMOTES: [(KeyCode, &str); 4] = [
+    (KeyCode::Key1, "Good game!"),
+    (KeyCode::Key2, "Nice one!"),
+    (KeyCode::Key3, "Oops..."),
+    (KeyCode::Key4, "Catch me if you can!"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatMessage {
+    Text(String),
+    Emote(u8),
+}
+
+impl ChatMessage {
+    /// Layout: magic, a kind byte (0 = text, 1 = emote), then the utf-8 text or the emote index.
+    pub fn encode(&self) -> Box<[u8]> {
+        let mut packet = CHAT_MAGIC.to_vec();
+        match self {
+            ChatMessage::Text(text) => {
+                packet.push(0);
+                packet.extend_from_slice(text.as_bytes());
+            }
+            ChatMessage::Emote(index) => {
+                packet.push(1);
+                packet.push(*index);
+            }
+        }
+        packet.into_boxed_slice()
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        match packet.strip_prefix(CHAT_MAGIC)? {
+            [0, text @ ..] => {
+                let text = String::from_utf8(text.to_vec()).ok()?;
+                Some(ChatMessage::Text(text.chars().take(MAX_MESSAGE_LEN).collect()))
+            }
+            [1, index] if (*index as usize) < EMOTES.len() => Some(ChatMessage::Emote(*index)),
+            _ => None,
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            ChatMessage::Text(text) => text.clone(),
+            ChatMessage::Emote(index) => format!("*{}*", EMOTES[*index as usize].1),
+        }
+    }
+}
+
+/// The chat box being typed in, opened with T and sent with Enter.
+/// Gameplay input is ignored while it's open.
+#[derive(Resource, Debug, Default)]
+pub struct ChatInput {
+    pub open: bool,
+    pub text: String,
+}
+
+/// Peers whose messages are hidden, toggled with `/mute <player>` and `/unmute <player>`.
+#[derive(Resource, Debug, Default)]
+pub struct ChatMutes(HashSet<String>);
+
+/// A line to add to the chat log.
+pub struct ChatLogEntry {
+    pub sender: String,
+    pub body: String,
+}
+
+#[derive(Component)]
+pub struct ChatLogUI;
+
+#[derive(Component)]
+pub struct ChatInputText;
+
+#[derive(Component)]
+pub struct ChatLine(Timer);
+
+/// Names peers the same way for everyone: "Player N", N being their GGRS handle + 1.
+fn player_label(socket: &PeerSocket, peer: &str) -> String {
+    let players = socket.0.lock().players();
+    players
+        .iter()
+        .position(|player| matches!(player, PlayerType::Remote(id) if id == peer))
+        .map_or_else(|| "Someone".to_owned(), |handle| format!("Player {}", handle + 1))
+}
+
+/// Finds the peer behind a "Player N" number typed in a chat command.
+fn peer_by_number(socket: &PeerSocket, number: &str) -> Option<String> {
+    let handle = number.trim().parse::<usize>().ok()?.checked_sub(1)?;
+    match socket.0.lock().players().get(handle)? {
+        PlayerType::Remote(peer) => Some(peer.clone()),
+        _ => None,
+    }
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+    // log, growing up from above the input line
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(8.),
+                    bottom: Val::Px(40.),
+                    ..default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexStart,
+                ..default()
+            },
+            ..default()
+        },
+        ChatLogUI,
+    ));
+
+    // input line
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(8.),
+                    bottom: Val::Px(8.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 20.,
+                    color: Color::BLACK,
+                },
+            ),
+            ..default()
+        },
+        ChatInputText,
+    ));
+}
+
+pub fn chat_keyboard_system(
+    keys: Res<Input<KeyCode>>,
+    mut char_evr: EventReader<ReceivedCharacter>,
+    socket: Option<Res<PeerSocket>>,
+    mut chat_input: ResMut<ChatInput>,
+    mut mutes: ResMut<ChatMutes>,
+    mut log: EventWriter<ChatLogEntry>,
+) {
+    // Read every frame, so keys typed while the box is closed don't show up once it opens
+    let typed: Vec<char> = char_evr.iter().map(|ev| ev.char).collect();
+
+    let Some(socket) = socket else {
+        // Nobody to talk to
+        if chat_input.open {
+            *chat_input = ChatInput::default();
+        }
+        return;
+    };
+
+    if !chat_input.open {
+        if keys.just_pressed(CHAT_KEY) {
+            chat_input.open = true;
+            return;
+        }
+        for (index, (key, _)) in EMOTES.iter().enumerate() {
+            if keys.just_pressed(*key) {
+                let emote = ChatMessage::Emote(index as u8);
+                socket.broadcast(&emote.encode());
+                log.send(ChatLogEntry {
+                    sender: "You".to_owned(),
+                    body: emote.display(),
+                });
+            }
+        }
+        return;
+    }
+
+    for char in typed {
+        if !char.is_control() && chat_input.text.chars().count() < MAX_MESSAGE_LEN {
+            chat_input.text.push(char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        chat_input.text.pop();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        *chat_input = ChatInput::default();
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+
+    let text = std::mem::take(&mut chat_input.text);
+    chat_input.open = false;
+    let text = text.trim();
+
+    let command = text
+        .strip_prefix("/mute ")
+        .map(|number| (true, number))
+        .or_else(|| text.strip_prefix("/unmute ").map(|number| (false, number)));
+    if let Some((mute, number)) = command {
+        let body = match peer_by_number(&socket, number) {
+            Some(peer) => {
+                let label = player_label(&socket, &peer);
+                if mute {
+                    mutes.0.insert(peer);
+                    format!("{label} is muted.")
+                } else {
+                    mutes.0.remove(&peer);
+                    format!("{label} is no longer muted.")
+                }
+            }
+            None => format!("There is no other player {}.", number.trim()),
+        };
+        log.send(ChatLogEntry {
+            sender: "Chat".to_owned(),
+            body,
+        });
+    } else if !text.is_empty() {
+        let message = ChatMessage::Text(text.to_owned());
+        socket.broadcast(&message.encode());
+        log.send(ChatLogEntry {
+            sender: "You".to_owned(),
+            body: message.display(),
+        });
+    }
+}
+
+pub fn receive_chat_system(
+    socket: Option<Res<PeerSocket>>,
+    mutes: Res<ChatMutes>,
+    mut packets: EventReader<PeerPacket>,
+    mut log: EventWriter<ChatLogEntry>,
+) {
+    let Some(socket) = socket else {
+        return;
+    };
+    for PeerPacket { peer, packet } in packets.iter() {
+        // Other out-of-band messages share the channel
+        let Some(message) = ChatMessage::decode(packet) else {
+            continue;
+        };
+        if mutes.0.contains(peer) {
+            continue;
+        }
+        log.send(ChatLogEntry {
+            sender: player_label(&socket, peer),
+            body: message.display(),
+        });
+    }
+}
+
+pub fn update_input_text(
+    chat_input: Res<ChatInput>,
+    mut query: Query<&mut Text, With<ChatInputText>>,
+) {
+    if !chat_input.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = if chat_input.open {
+            format!("Say: {}_", chat_input.text)
+        } else {
+            String::new()
+        };
+    }
+}
+
+pub fn spawn_log_lines(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut entries: EventReader<ChatLogEntry>,
+    log_query: Query<Entity, With<ChatLogUI>>,
+    line_query: Query<(Entity, &ChatLine)>,
+) {
+    let Ok(log_entity) = log_query.get_single() else {
+        return;
+    };
+
+    let mut lines: Vec<(Entity, f32)> = line_query
+        .iter()
+        .map(|(entity, line)| (entity, line.0.elapsed_secs()))
+        .collect();
+    // Oldest first
+    lines.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    for ChatLogEntry { sender, body } in entries.iter() {
+        if lines.len() >= MAX_LOG_LINES {
+            let (oldest, _) = lines.remove(0);
+            commands.entity(oldest).despawn_recursive();
+        }
+
+        let line = commands
+            .spawn((
+                TextBundle::from_section(
+                    format!("{sender}: {body}"),
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 20.,
+                        color: Color::BLACK,
+                    },
+                ),
+                ChatLine(Timer::from_seconds(LINE_LIFETIME_SECS, TimerMode::Once)),
+            ))
+            .id();
+        commands.entity(log_entity).add_child(line);
+        lines.push((line, 0.));
+    }
+}
+
+pub fn fade_log_lines(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut ChatLine, &mut Text)>,
+) {
+    for (entity, mut line, mut text) in query.iter_mut() {
+        line.0.tick(time.delta());
+        if line.0.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let alpha = (line.0.remaining_secs() / LINE_FADE_SECS).min(1.);
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
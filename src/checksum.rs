@@ -34,3 +34,16 @@ fn fletcher16(data: &[u8]) -> u16 {
 
     (sum2 << 8) | sum1
 }
+
+/// Computes the 64-bit FNV-1a hash, used where the hash has to match between builds and platforms:
+/// <https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function>
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    hash
+}
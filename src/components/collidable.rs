@@ -1,63 +1,37 @@
 use bevy::{
-    prelude::{Component},
+    prelude::Component,
     ecs::bundle::Bundle,
 };
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-#[derive(Clone, Debug, Default, LdtkIntCell, Component)]
+/// Rapier body of a player, for a top-down game: no gravity and no contact forces.
+///
+/// The body is kinematic and follows the transform [`move_players`] sets on the grid of the
+/// arena. Rapier isn't rollback-safe, so nothing in a round depends on what it computes:
+/// walls, blasts and pickups are all checked against the cells of the arena instead.
+///
+/// [`move_players`]: crate::player::move_players
+#[derive(Clone, Debug, Bundle)]
 pub struct ColliderBundle {
     pub collider: Collider,
     pub rigid_body: RigidBody,
-    pub velocity: Velocity,
     pub rotation_constraints: LockedAxes,
     pub gravity_scale: GravityScale,
-    pub friction: Friction,
-    pub density: ColliderMassProperties,
 }
 
-impl From<&EntityInstance> for ColliderBundle {
-    fn from(entity_instance: &EntityInstance) -> ColliderBundle {
-        let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-
-        match entity_instance.identifier.as_ref() {
-            "Player" => ColliderBundle {
-                collider: Collider::cuboid(6., 14.),
-                rigid_body: RigidBody::Dynamic,
-                friction: Friction {
-                    coefficient: 0.0,
-                    combine_rule: CoefficientCombineRule::Min,
-                },
-                rotation_constraints,
-                ..Default::default()
-            },
-            "Mob" => ColliderBundle {
-                collider: Collider::cuboid(5., 5.),
-                rigid_body: RigidBody::KinematicVelocityBased,
-                rotation_constraints,
-                ..Default::default()
-            },
-            "Chest" => ColliderBundle {
-                collider: Collider::cuboid(8., 8.),
-                rigid_body: RigidBody::Dynamic,
-                rotation_constraints,
-                gravity_scale: GravityScale(1.0),
-                friction: Friction::new(0.5),
-                density: ColliderMassProperties::Density(15.0),
-                ..Default::default()
-            },
-            _ => {
-                ColliderBundle::default()
-            },
+impl ColliderBundle {
+    pub fn player() -> Self {
+        Self {
+            // Players are drawn at a tenth of their size, this is most of a cell
+            collider: Collider::cuboid(10., 10.),
+            rigid_body: RigidBody::KinematicPositionBased,
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            gravity_scale: GravityScale(0.),
         }
     }
 }
 
-#[derive(Clone, Default, Component)]
-pub struct WallDetection {
-    pub facing_wall: bool,
-}
-
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Wall;
 
@@ -1,64 +1,214 @@
 use bevy::{
-    prelude::{Component, Vec3}, 
-    time::{Timer, TimerMode}, 
+    prelude::{Color, Component, IVec2},
+    reflect::Reflect,
     sprite::SpriteBundle,
     ecs::bundle::Bundle
 };
 use bevy_ecs_ldtk::Worldly;
-use bevy_ggrs::Rollback;
-use crate::checksum::Checksum;
+use bevy_ggrs::{ggrs::PlayerHandle, Rollback};
+use serde::Deserialize;
+use crate::{checksum::Checksum, input::PlayerInput, resources::RoundRng};
 use super::{collidable::{ColliderBundle}, online::RoundEntity};
 
-#[derive(Component)]
+/// Frames left before the bomb goes off. Bombs placed with the remote control have none.
+#[derive(Component, Reflect, Default)]
 pub struct FuseTime {
-    /// track when the bomb should explode (non-repeating timer).
-    pub timer: Timer,
+    pub frames: u32,
 }
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
 pub struct Player {
     pub handle: usize,
 }
-#[derive(Component)]
-pub struct Explosion;
-#[derive(Component)]
-pub struct ExplosionToSpawn(pub Vec3);
-#[derive(Component)]
-pub struct ExplosionTimer(pub Timer);
 
-impl Default for ExplosionTimer {
+/// The way a player last walked, where they punch and throw bombs.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+pub struct Facing {
+    pub direction: IVec2,
+}
+
+impl Default for Facing {
     fn default() -> Self {
-        Self(Timer::from_seconds(0.05, TimerMode::Repeating))
+        Self {
+            direction: IVec2::new(0, -1),
+        }
+    }
+}
+
+/// A player run by a bot inside the rollback simulation, with everything the bot remembers.
+#[derive(Component, Reflect, Default, Clone)]
+pub struct BotControl {
+    /// What the bot presses on this frame
+    pub input: PlayerInput,
+    pub rng: RoundRng,
+    /// Going after players rather than soft blocks
+    pub hunting: bool,
+    /// Inputs decided but not pressed yet, to play with a reaction time
+    pub delayed: Vec<PlayerInput>,
+}
+
+impl BotControl {
+    /// Every peer seeds the bot of a handle the same way.
+    pub fn new(session_seed: u64, handle: PlayerHandle) -> Self {
+        Self {
+            rng: RoundRng::new(session_seed ^ (handle as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+            ..Default::default()
+        }
+    }
+}
+
+/// A bomb on the field, snapped to the center of its cell.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Bomb {
+    /// Handle of the player who placed it
+    pub owner: usize,
+    /// How many cells the blast reaches in each direction
+    pub range: i32,
+    pub kind: BombKind,
+}
+
+/// What a bomb does beyond blowing up, see [`BombBag::kind`].
+#[derive(Reflect, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BombKind {
+    #[default]
+    Normal,
+    /// Blows through every soft block in its range
+    Pierce,
+    /// Hides once armed and goes off when a player steps on it, see [`LandMine`]
+    LandMine,
+}
+
+/// Frames left before a land mine is armed and hidden. Land mines have no [`FuseTime`] until a
+/// player steps on them, and players and other bombs pass over them.
+#[derive(Component, Reflect, Default)]
+pub struct LandMine {
+    pub frames: u32,
+}
+
+impl LandMine {
+    pub fn is_armed(&self) -> bool {
+        self.frames == 0
     }
 }
 
-#[derive(Component)]
-pub struct BombSlot {
-    pub bomb: Option<Bomb>,
+/// Which way a kicked bomb slides, zero while it rests. Every bomb has one.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Sliding {
+    pub direction: IVec2,
 }
 
-#[derive(Component)]
-pub struct Bomb;
+/// A bomb off the ground, which players walk under and blasts don't set off, though its fuse
+/// keeps running. Every bomb has one.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Airborne {
+    /// Lifted by the player with the handle [`Airborne::holder`]
+    pub carried: bool,
+    pub holder: usize,
+    /// Which way it flies once thrown or punched, zero otherwise
+    pub direction: IVec2,
+    /// How far it flies before trying to land, in world units
+    pub distance: f32,
+}
 
-#[derive(Component)]
+impl Airborne {
+    pub fn is_grounded(&self) -> bool {
+        !self.carried && self.direction == IVec2::ZERO
+    }
+}
+
+/// How many bombs a player can have on the field at once, and how far they blow.
+#[derive(Component, Reflect, Clone, Debug)]
 pub struct BombBag {
-    pub slots: [BombSlot; 9],
+    pub capacity: usize,
+    pub range: i32,
+    /// Walking into a bomb sends it sliding
+    pub kick: bool,
+    /// Power glove: lifts and throws bombs
+    pub glove: bool,
+    /// Boxing glove: punches bombs over whatever is in the way
+    pub punch: bool,
+    /// Remote control: bombs placed have no fuse and go off on [`InputAction::Detonate`]
+    ///
+    /// [`InputAction::Detonate`]: crate::input::InputAction::Detonate
+    pub remote: bool,
+    /// Detonate was held on the last frame, so holding it sets off a single bomb
+    pub detonating: bool,
+    /// Kind of the bombs placed, from the last of the pierce and land mine power-ups
+    pub kind: BombKind,
+    /// Line bomb: placing a bomb lays every bomb left in a row, the way the player faces
+    pub line: bool,
 }
 
 impl Default for BombBag {
     fn default() -> Self {
         Self {
-            slots: [
-                BombSlot { bomb: Some(Bomb) },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-                BombSlot { bomb: None },
-            ],
+            capacity: 1,
+            range: 2,
+            kick: false,
+            glove: false,
+            punch: false,
+            remote: false,
+            detonating: false,
+            kind: BombKind::Normal,
+            line: false,
+        }
+    }
+}
+
+/// A burning cell, deadly to players until it goes out.
+#[derive(Component, Reflect, Default)]
+pub struct Flame {
+    pub frames: u32,
+}
+
+/// A pickup left behind by a soft block.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum PowerUp {
+    /// One more bomb in the [`BombBag`]
+    #[default]
+    ExtraBomb,
+    /// One more cell of blast range
+    BlastRange,
+    /// Kicking bombs, see [`BombBag::kick`]
+    Kick,
+    /// Throwing bombs, see [`BombBag::glove`]
+    Glove,
+    /// Punching bombs, see [`BombBag::punch`]
+    BoxingGlove,
+    /// Setting bombs off by hand, see [`BombBag::remote`]
+    Remote,
+    /// Pierce bombs, see [`BombKind::Pierce`]
+    Pierce,
+    /// Laying bombs in a row, see [`BombBag::line`]
+    LineBomb,
+    /// Land mines, see [`BombKind::LandMine`]
+    LandMine,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 9] = [
+        PowerUp::ExtraBomb,
+        PowerUp::BlastRange,
+        PowerUp::Kick,
+        PowerUp::Glove,
+        PowerUp::BoxingGlove,
+        PowerUp::Remote,
+        PowerUp::Pierce,
+        PowerUp::LineBomb,
+        PowerUp::LandMine,
+    ];
+
+    pub fn color(self) -> Color {
+        match self {
+            PowerUp::ExtraBomb => Color::rgb(0.2, 0.4, 1.),
+            PowerUp::BlastRange => Color::rgb(1., 0.5, 0.),
+            PowerUp::Kick => Color::rgb(0.6, 0.2, 0.9),
+            PowerUp::Glove => Color::rgb(1., 1., 1.),
+            PowerUp::BoxingGlove => Color::rgb(0.9, 0.1, 0.2),
+            PowerUp::Remote => Color::rgb(0.2, 0.8, 0.3),
+            PowerUp::Pierce => BombKind::Pierce.color(),
+            PowerUp::LineBomb => Color::rgb(1., 0.9, 0.1),
+            PowerUp::LandMine => BombKind::LandMine.color(),
         }
     }
 }
@@ -67,6 +217,8 @@ impl Default for BombBag {
 pub struct PlayerBundle {
     pub player_sprite: SpriteBundle,
     pub player: Player,
+    pub facing: Facing,
+    pub bomb_bag: BombBag,
     pub check_sum: Checksum,
     pub rollback: Rollback,
     pub round_entity: RoundEntity,
@@ -75,4 +227,4 @@ pub struct PlayerBundle {
     //#[from_entity_instance]
     #[bundle]
     pub collider_bundle: ColliderBundle,
-}
\ No newline at end of file
+}
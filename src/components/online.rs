@@ -1,17 +1,22 @@
 
 use bevy::{
     prelude::{Component},
+    reflect::Reflect,
 };
 use bevy_ggrs::ggrs::Config;
 
-#[derive(Component)]
+use crate::input::PlayerInput;
+
+/// Despawned when the round ends. It's rollback state, so entities a rollback brings back are
+/// cleaned up too.
+#[derive(Component, Reflect, Default)]
 pub struct RoundEntity;
 
 //Not exactly considered as components.
 pub struct GGRSConfig;
 impl Config for GGRSConfig {
-    // 4-directions + fire fits easily in a single byte
-    type Input = u8;
+    // 4 directions + 4 actions, with room to grow
+    type Input = PlayerInput;
     type State = u8;
     // Matchbox' WebRtcSocket addresses are strings
     type Address = String;
@@ -23,6 +28,9 @@ pub enum AppState {
     MenuMain,
     MenuOnline,
     MenuConnect,
+    MenuJoin,
+    MenuControls,
+    MenuLevels,
     RoundLocal,
     RoundOnline,
 }
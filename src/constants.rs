@@ -1,7 +1,7 @@
-pub(crate) const INPUT_UP: u8 = 1 << 0;
-pub(crate) const INPUT_DOWN: u8 = 1 << 1;
-pub(crate) const INPUT_LEFT: u8 = 1 << 2;
-pub(crate) const INPUT_RIGHT: u8 = 1 << 3;
-pub(crate) const INPUT_FIRE: u8 = 1 << 4;
-
-pub(crate) const MAX_PLAYERS: usize = 2;
\ No newline at end of file
+/// Seats in a round, one per spawn corner of the arena.
+pub(crate) const MAX_PLAYERS: usize = 4;
+/// Peers needed to start an online round.
+pub(crate) const ONLINE_PLAYERS: usize = 2;
+pub(crate) const FPS: usize = 60;
+/// Scale of the LDtk world, so one 16px tile is 2.56 world units.
+pub(crate) const LEVEL_SCALE: f32 = 0.16;
@@ -0,0 +1,119 @@
+use crate::{
+    components::{
+        game_elements::{BotControl, Player},
+        online::RoundEntity,
+    },
+    resources::{FontAssets, Ruleset},
+    sudden_death::{clock_text, RoundClock, TimeUp},
+};
+use bevy::prelude::*;
+
+/// Time left in the round, at the top of the screen.
+#[derive(Component)]
+pub struct RoundTimerText;
+
+/// What [`log_round_system`] has seen of the round so far.
+#[derive(Resource, Default)]
+pub struct RoundLog {
+    started: bool,
+    players: Vec<usize>,
+    bots: Vec<usize>,
+    time_up: bool,
+}
+
+pub fn setup_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands.insert_resource(RoundLog::default());
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(12.),
+                    left: Val::Percent(50.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                },
+            ),
+            ..default()
+        },
+        RoundTimerText,
+        RoundEntity,
+    ));
+}
+
+/// Counts down from the rollback frames, so the timer follows rollbacks too.
+pub fn update_round_timer_text(
+    ruleset: Res<Ruleset>,
+    clock: Res<RoundClock>,
+    mut query: Query<&mut Text, With<RoundTimerText>>,
+) {
+    let remaining = clock.remaining(&ruleset);
+    let (value, color) = if remaining > 0 {
+        // Rounded up, so the timer reads 0:00 when time's up and not a second before
+        let seconds = remaining.div_ceil(ruleset.fps as u32);
+        let color = if seconds <= 10 { Color::ORANGE } else { Color::WHITE };
+        (clock_text(seconds), color)
+    } else {
+        match ruleset.time_up {
+            TimeUp::SuddenDeath => ("Sudden death!".to_owned(), Color::RED),
+            TimeUp::Draw => ("Time's up, draw!".to_owned(), Color::RED),
+        }
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+            text.sections[0].style.color = color;
+        }
+    }
+}
+
+/// Logs players going out, bots taking over and time running out.
+///
+/// The rollback schedule runs frames again after every rollback, so it would log them more than
+/// once. This runs once a frame on the state the rollbacks settled on.
+pub fn log_round_system(
+    mut log: ResMut<RoundLog>,
+    ruleset: Res<Ruleset>,
+    clock: Res<RoundClock>,
+    player_query: Query<(&Player, Option<&BotControl>)>,
+) {
+    let mut players = Vec::new();
+    let mut bots = Vec::new();
+    for (player, bot) in player_query.iter() {
+        players.push(player.handle);
+        if bot.is_some() {
+            bots.push(player.handle);
+        }
+    }
+    players.sort_unstable();
+    bots.sort_unstable();
+
+    if log.started {
+        for handle in log.players.iter().filter(|handle| !players.contains(handle)) {
+            info!("Player {} is out", handle + 1);
+        }
+        for handle in bots.iter().filter(|handle| !log.bots.contains(handle)) {
+            info!("A bot takes over for player {}", handle + 1);
+        }
+    }
+    if !log.time_up && clock.remaining(&ruleset) == 0 {
+        match ruleset.time_up {
+            TimeUp::SuddenDeath => info!("Time's up, sudden death"),
+            TimeUp::Draw => info!("Time's up, the round is a draw"),
+        }
+        log.time_up = true;
+    }
+    if !log.started || log.players != players || log.bots != bots {
+        log.started = true;
+        log.players = players;
+        log.bots = bots;
+    }
+}
@@ -1,44 +1,379 @@
-use crate::constants::{INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_RIGHT, INPUT_UP};
+use std::collections::HashMap;
+
+use crate::{
+    chat::ChatInput,
+    components::{
+        game_elements::BotControl,
+        online::{AppState, GGRSConfig},
+    },
+    resources::{GamepadAssignments, LocalHandles, LocalKeymaps},
+    touch::TouchControls,
+};
 use bevy::prelude::*;
-use bevy_ggrs::ggrs::PlayerHandle;
+use bevy_ggrs::{
+    ggrs::{InputStatus, PlayerHandle},
+    PlayerInputs,
+};
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+/// Everything a player can press, each one a bit of [`PlayerInput`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Place a bomb
+    Fire,
+    /// Set off remote-controlled bombs
+    Detonate,
+    /// Kick or punch the bomb in front of the player
+    Punch,
+    Emote,
+    /// Hold to lift a bomb with the power glove, let go to throw it
+    Grab,
+}
 
-pub fn handle_input_system(_: In<PlayerHandle>, keys: Res<Input<KeyCode>>) -> u8 {
-    let mut input: u8 = 0;
+impl InputAction {
+    pub const ALL: [InputAction; 9] = [
+        InputAction::Up,
+        InputAction::Down,
+        InputAction::Left,
+        InputAction::Right,
+        InputAction::Fire,
+        InputAction::Detonate,
+        InputAction::Punch,
+        InputAction::Emote,
+        InputAction::Grab,
+    ];
 
-    if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
-        input |= INPUT_UP;
+    fn bit(self) -> u16 {
+        1 << self as u16
     }
-    if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
-        input |= INPUT_DOWN;
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputAction::Up => "Up",
+            InputAction::Down => "Down",
+            InputAction::Left => "Left",
+            InputAction::Right => "Right",
+            InputAction::Fire => "Bomb",
+            InputAction::Detonate => "Detonate",
+            InputAction::Punch => "Kick / Punch",
+            InputAction::Emote => "Emote",
+            InputAction::Grab => "Grab / Throw",
+        }
     }
-    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
-        input |= INPUT_LEFT
+}
+
+/// What a player pressed on a frame, as sent through GGRS.
+///
+/// Only read it through the methods, so changing the layout stays local to this type.
+/// Bump [`PlayerInput::VERSION`] whenever the layout or the meaning of a bit changes: it's part
+/// of the connection handshake, so peers with different encodings refuse each other.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable, Reflect, FromReflect)]
+pub struct PlayerInput {
+    /// One bit per [`InputAction`], 9 of 16 in use
+    buttons: u16,
+}
+
+impl PlayerInput {
+    pub const VERSION: u8 = 2;
+
+    pub fn press(&mut self, action: InputAction) {
+        self.buttons |= action.bit();
     }
-    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
-        input |= INPUT_RIGHT;
+
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.buttons & action.bit() != 0
     }
-    if keys.any_pressed([KeyCode::Space, KeyCode::Z]) {
-        input |= INPUT_FIRE;
+
+    /// Presses everything pressed in `other` as well.
+    pub fn merge(&mut self, other: PlayerInput) {
+        self.buttons |= other.buttons;
     }
 
-    input
+    /// The direction the player is pushing towards, not normalized.
+    pub fn direction(&self) -> Vec2 {
+        let mut direction = Vec2::ZERO;
+
+        if self.pressed(InputAction::Up) {
+            direction.y += 1.;
+        }
+        if self.pressed(InputAction::Down) {
+            direction.y -= 1.;
+        }
+        if self.pressed(InputAction::Right) {
+            direction.x += 1.;
+        }
+        if self.pressed(InputAction::Left) {
+            direction.x -= 1.;
+        }
+
+        direction
+    }
 }
 
-pub fn control_direction(input: &u8) -> Vec2 {
-    let mut direction = Vec2::ZERO;
+/// The keys bound to each action for one player on the keyboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keymap(HashMap<InputAction, Vec<KeyCode>>);
 
-    if input & INPUT_UP != 0 {
-        direction.y += 1.;
+impl Keymap {
+    /// WASD to move, Space to place bombs.
+    pub fn left_hand() -> Self {
+        Self(HashMap::from([
+            (InputAction::Up, vec![KeyCode::W]),
+            (InputAction::Down, vec![KeyCode::S]),
+            (InputAction::Left, vec![KeyCode::A]),
+            (InputAction::Right, vec![KeyCode::D]),
+            (InputAction::Fire, vec![KeyCode::Space, KeyCode::Z]),
+            (InputAction::Detonate, vec![KeyCode::LShift, KeyCode::X]),
+            (InputAction::Punch, vec![KeyCode::LControl, KeyCode::C]),
+            (InputAction::Emote, vec![KeyCode::E]),
+            (InputAction::Grab, vec![KeyCode::Q]),
+        ]))
     }
-    if input & INPUT_DOWN != 0 {
-        direction.y -= 1.;
+
+    /// Arrows to move, Enter to place bombs.
+    pub fn right_hand() -> Self {
+        Self(HashMap::from([
+            (InputAction::Up, vec![KeyCode::Up]),
+            (InputAction::Down, vec![KeyCode::Down]),
+            (InputAction::Left, vec![KeyCode::Left]),
+            (InputAction::Right, vec![KeyCode::Right]),
+            (InputAction::Fire, vec![KeyCode::Return]),
+            (InputAction::Detonate, vec![KeyCode::RShift]),
+            (InputAction::Punch, vec![KeyCode::RControl]),
+            (InputAction::Emote, vec![KeyCode::RAlt]),
+            (InputAction::Grab, vec![KeyCode::Slash]),
+        ]))
+    }
+
+    pub fn bindings(&self, action: InputAction) -> &[KeyCode] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Makes `key` the only key of `action`.
+    pub fn bind(&mut self, action: InputAction, key: KeyCode) {
+        self.0.insert(action, vec![key]);
     }
-    if input & INPUT_RIGHT != 0 {
-        direction.x += 1.;
+
+    /// Every key bound to an action.
+    pub fn keys(&self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.0.values().flatten().copied()
     }
-    if input & INPUT_LEFT != 0 {
-        direction.x -= 1.;
+
+    /// Takes the bindings of every action in `saved`. Actions `saved` doesn't know, like ones
+    /// added since it was saved, keep their keys here unless they're `taken`.
+    pub fn merge(&mut self, saved: Keymap, taken: &[KeyCode]) {
+        for (action, keys) in self.0.iter_mut() {
+            if !saved.0.contains_key(action) {
+                keys.retain(|key| !taken.contains(key));
+            }
+        }
+        self.0.extend(saved.0);
     }
 
-    direction
+    pub fn action_of(&self, key: KeyCode) -> Option<InputAction> {
+        self.0
+            .iter()
+            .find(|(_, bound)| bound.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    fn read_into(&self, keys: &Input<KeyCode>, input: &mut PlayerInput) {
+        for (action, bound) in self.0.iter() {
+            if keys.any_pressed(bound.iter().copied()) {
+                input.press(*action);
+            }
+        }
+    }
+}
+
+/// How far the left stick has to be pushed before it counts as a direction.
+const STICK_DEADZONE: f32 = 0.35;
+
+const GAMEPAD_BUTTONS: [(GamepadButtonType, InputAction); 9] = [
+    (GamepadButtonType::DPadUp, InputAction::Up),
+    (GamepadButtonType::DPadDown, InputAction::Down),
+    (GamepadButtonType::DPadLeft, InputAction::Left),
+    (GamepadButtonType::DPadRight, InputAction::Right),
+    (GamepadButtonType::South, InputAction::Fire),
+    (GamepadButtonType::East, InputAction::Detonate),
+    (GamepadButtonType::West, InputAction::Punch),
+    (GamepadButtonType::North, InputAction::Emote),
+    (GamepadButtonType::RightTrigger, InputAction::Grab),
+];
+
+fn read_gamepad(
+    gamepad: Gamepad,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    input: &mut PlayerInput,
+) {
+    for (button_type, action) in GAMEPAD_BUTTONS {
+        if buttons.pressed(GamepadButton::new(gamepad, button_type)) {
+            input.press(action);
+        }
+    }
+
+    let axis = |axis_type| {
+        axes.get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or_default()
+    };
+    let (x, y) = (
+        axis(GamepadAxisType::LeftStickX),
+        axis(GamepadAxisType::LeftStickY),
+    );
+    if y > STICK_DEADZONE {
+        input.press(InputAction::Up);
+    } else if y < -STICK_DEADZONE {
+        input.press(InputAction::Down);
+    }
+    if x > STICK_DEADZONE {
+        input.press(InputAction::Right);
+    } else if x < -STICK_DEADZONE {
+        input.press(InputAction::Left);
+    }
+}
+
+/// What a player presses on the current rollback frame: their bot's choice if a bot runs
+/// them, or their GGRS input. Disconnected players do nothing until a bot takes over.
+///
+/// There are no GGRS inputs at all in headless bot-only rounds, see [`crate::tournament`].
+pub fn player_input(
+    inputs: Option<&PlayerInputs<GGRSConfig>>,
+    handle: PlayerHandle,
+    bot: Option<&BotControl>,
+) -> PlayerInput {
+    if let Some(bot) = bot {
+        return bot.input;
+    }
+    match inputs.and_then(|inputs| inputs.get(handle)) {
+        Some((input, InputStatus::Confirmed | InputStatus::Predicted)) => *input,
+        _ => PlayerInput::default(),
+    }
+}
+
+/// Keeps controller assignments in sync when controllers are plugged in or out, even mid-match.
+pub fn gamepad_connection_system(
+    mut gamepad_evr: EventReader<GamepadEvent>,
+    state: Res<State<AppState>>,
+    mut assignments: ResMut<GamepadAssignments>,
+) {
+    let in_round = matches!(state.current(), AppState::RoundLocal | AppState::RoundOnline);
+
+    for GamepadEvent { gamepad, event_type } in gamepad_evr.iter() {
+        match event_type {
+            // Outside of rounds, controllers join from the join screen
+            GamepadEventType::Connected(_) if in_round => {
+                // A controller plugged in mid-match takes over the first seat left without one
+                if let Some(index) = assignments.assign(*gamepad) {
+                    info!("Controller {} plays for player {}", gamepad.id, index + 1);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                if let Some(index) = assignments.unassign(*gamepad) {
+                    warn!(
+                        "Controller {} of player {} disconnected, their keys still work",
+                        gamepad.id,
+                        index + 1
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_input_system(
+    In(handle): In<PlayerHandle>,
+    keys: Res<Input<KeyCode>>,
+    chat_input: Res<ChatInput>,
+    keymaps: Res<LocalKeymaps>,
+    local_handles: Option<Res<LocalHandles>>,
+    assignments: Res<GamepadAssignments>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    touch_controls: Res<TouchControls>,
+) -> PlayerInput {
+    let mut input = PlayerInput::default();
+
+    // Keys typed into the chat box shouldn't move the player
+    if chat_input.open {
+        return input;
+    }
+
+    let local_handles = local_handles
+        .as_deref()
+        .map_or(&[][..], |local| &local.handles[..]);
+    if local_handles.len() <= 1 {
+        // Alone on this machine, every keymap and controller works
+        for keymap in keymaps.0.iter() {
+            keymap.read_into(&keys, &mut input);
+        }
+        for gamepad in gamepads.iter() {
+            read_gamepad(gamepad, &gamepad_buttons, &gamepad_axes, &mut input);
+        }
+        input.merge(touch_controls.input);
+    } else if let Some(index) = local_handles.iter().position(|local| *local == handle) {
+        if let Some(keymap) = keymaps.0.get(index) {
+            keymap.read_into(&keys, &mut input);
+        }
+        if let Some(gamepad) = assignments.gamepad(index) {
+            read_gamepad(gamepad, &gamepad_buttons, &gamepad_axes, &mut input);
+        }
+        // There is only one screen to touch, it belongs to the first player
+        if index == 0 {
+            input.merge(touch_controls.input);
+        }
+    }
+
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_action_has_its_own_bit() {
+        assert_eq!(std::mem::size_of::<PlayerInput>(), 2);
+        for (index, action) in InputAction::ALL.into_iter().enumerate() {
+            assert_eq!(action.bit(), 1 << index, "{action:?}");
+
+            let mut input = PlayerInput::default();
+            input.press(action);
+            for other in InputAction::ALL {
+                assert_eq!(input.pressed(other), other == action);
+            }
+        }
+    }
+
+    #[test]
+    fn input_survives_the_trip_through_ggrs() {
+        let mut input = PlayerInput::default();
+        input.press(InputAction::Up);
+        input.press(InputAction::Grab);
+        let bytes = bytemuck::bytes_of(&input);
+        assert_eq!(u16::from_ne_bytes([bytes[0], bytes[1]]), 0b1_0000_0001);
+        assert_eq!(*bytemuck::from_bytes::<PlayerInput>(bytes), input);
+    }
+
+    #[test]
+    fn opposite_directions_cancel_out() {
+        let mut input = PlayerInput::default();
+        input.press(InputAction::Up);
+        input.press(InputAction::Left);
+        assert_eq!(input.direction(), Vec2::new(-1., 1.));
+
+        let mut other = PlayerInput::default();
+        other.press(InputAction::Down);
+        input.merge(other);
+        assert_eq!(input.direction(), Vec2::new(-1., 0.));
+    }
 }
@@ -0,0 +1,21 @@
+//! The game's modules, shared by the game binary and the integration tests in `tests/`.
+
+pub mod arena;
+pub mod bomb;
+pub mod bot;
+pub mod chat;
+pub mod checksum;
+pub mod components;
+pub mod constants;
+pub mod hud;
+pub mod input;
+pub mod player;
+pub mod resources;
+pub mod settings;
+pub mod sudden_death;
+pub mod touch;
+pub mod tournament;
+pub mod validate;
+pub mod menu;
+pub mod network;
+pub mod plugins;
@@ -1,27 +1,37 @@
-mod bomb;
-mod checksum;
-mod components;
-mod constants;
-mod input;
-mod player;
-mod resources;
-mod menu;
-mod plugins;
-
 use std::time::Duration;
 use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
     prelude::*
 };
-use plugins::{
+use bomberboy::{tournament, validate};
+use bomberboy::plugins::{
     connections_plugin, 
     asset_plugin, 
+    bot_plugin::BotPlugin,
     ggrsp_plugin::init_ggrsp_plugin, 
     camera_plugin::CameraPlugin,
+    chat_plugin::ChatPlugin,
+    controls_plugin::ControlsPlugin,
+    hud_plugin::HudPlugin,
     levels_plugin::LevelsPlugin
 };
 
 fn main() {
+    if let Some(project) = validate::project_from_args() {
+        if let Err(err) = validate::run(&project) {
+            eprintln!("Level check failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(config) = tournament::config_from_args() {
+        if let Err(err) = tournament::run(&config) {
+            eprintln!("Tournament failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut app = App::new();
 
     init_ggrsp_plugin(&mut app);
@@ -37,7 +47,14 @@ fn main() {
         .add_plugin(connections_plugin::MainMenuPlugin)
         .add_plugin(connections_plugin::ConnectMenuPlugin)
         .add_plugin(connections_plugin::OnlineMenuPlugin)
+        .add_plugin(connections_plugin::JoinMenuPlugin)
+        .add_plugin(connections_plugin::ControlsMenuPlugin)
+        .add_plugin(connections_plugin::LevelsMenuPlugin)
         .add_plugin(connections_plugin::LocalMatchPlugin)
         .add_plugin(connections_plugin::OnlineMatchPlugin)
+        .add_plugin(ChatPlugin)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(BotPlugin)
+        .add_plugin(HudPlugin)
         .run();
 }
\ No newline at end of file
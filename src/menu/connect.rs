@@ -1,17 +1,34 @@
 use bevy::{prelude::*, tasks::IoTaskPool};
+use bevy_ecs_ldtk::prelude::LevelSelection;
 use bevy_ggrs::{ggrs, Session as SessionType};
 use ggrs::{PlayerType, SessionBuilder};
-use matchbox_socket::WebRtcSocket;
+use matchbox_socket::{ChannelConfig, WebRtcSocket, WebRtcSocketConfig};
 
 use crate::{
-    constants::MAX_PLAYERS,
-    resources::{ConnectData, FontAssets, LocalHandles, Session, LobbyID},
+    bomb::PowerUpTable,
+    bot::{BotPlayers, BotProfile},
+    checksum::fnv1a64,
+    constants::{FPS, ONLINE_PLAYERS},
+    network::{
+        handshake::{Handshake, ProtocolInfo},
+        loopback::LoopbackNetwork,
+        simulation::SimulatedSocket,
+        MatchSocket, PeerPacket, PeerSocket, SharedSocket,
+    },
+    plugins::levels_plugin::Levels,
+    resources::{
+        ConnectData, DebugConfig, FontAssets, LobbyID, LocalHandles, NetworkConditions, RoundRng,
+        Ruleset, Session, SessionSeed,
+    },
     components::{online::{GGRSConfig, AppState}},
 };
 
 #[derive(Component)]
 pub struct MenuConnectUI;
 
+#[derive(Component)]
+pub struct ConnectStatusText;
+
 #[derive(Component)]
 pub enum MenuConnectBtn {
     Back,
@@ -19,40 +36,117 @@ pub enum MenuConnectBtn {
 
 const MATCHBOX_ADDR: &str = "ws://127.0.0.1:3536";
 
-pub fn create_matchbox_socket(mut commands: Commands, connect_data: Res<ConnectData>) {
+pub fn create_matchbox_socket(
+    mut commands: Commands,
+    connect_data: Res<ConnectData>,
+    ruleset: Res<Ruleset>,
+    power_ups: Res<PowerUpTable>,
+    levels: Res<Levels>,
+    selection: Res<LevelSelection>,
+    loopback: Option<Res<LoopbackNetwork>>,
+) {
     let lobby_id = &connect_data.lobby_id;
-    let room_url = format!("{MATCHBOX_ADDR}/{lobby_id}");
-    let (socket, message_loop) = WebRtcSocket::new(room_url);
-    IoTaskPool::get().spawn(message_loop).detach();
+    let socket = match loopback {
+        // Peers of this process meet directly, no signalling server needed
+        Some(network) => SharedSocket::new(network.join(lobby_id)),
+        None => {
+            let room_url = format!("{MATCHBOX_ADDR}/{lobby_id}");
+            // The GGRS channel first, as the ggrs socket expects, then RELIABLE_CHANNEL
+            let (socket, message_loop) = WebRtcSocket::new_with_config(WebRtcSocketConfig {
+                room_url,
+                channels: vec![ChannelConfig::unreliable(), ChannelConfig::reliable()],
+                ..default()
+            });
+            IoTaskPool::get().spawn(message_loop).detach();
+            SharedSocket::new(socket)
+        }
+    };
+    commands.insert_resource(PeerSocket(socket.clone()));
     commands.insert_resource(Session {
         socket: Some(socket),
     });
+    let level = levels
+        .selected(&selection)
+        .map_or("", |level| level.identifier.as_str());
+    commands.insert_resource(Handshake::new(ProtocolInfo::local(&ruleset, &power_ups, level)));
     commands.remove_resource::<ConnectData>();
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_matchbox_socket(
     commands: Commands,
     mut state: ResMut<State<AppState>>,
     mut session: ResMut<Session>,
-    lobby_id: Option<Res<LobbyID>>
+    mut handshake: ResMut<Handshake>,
+    mut packets: EventReader<PeerPacket>,
+    lobby_id: Option<Res<LobbyID>>,
+    ruleset: Res<Ruleset>,
+    levels: Res<Levels>,
+    selection: Res<LevelSelection>,
+    debug_config: Res<DebugConfig>,
 ) {
-    let Some(socket) = &mut session.socket else {
+    let Some(socket) = &session.socket else {
         // If there is no socket we've already started the game
         return;
     };
-    socket.accept_new_connections();
-    if socket.players().len() >= MAX_PLAYERS {
+    let mut socket = socket.lock();
+    handshake.update(&mut **socket, packets.iter());
+    // Only start once every peer in the room runs a compatible build
+    let players = socket.players();
+    let ready =
+        players.len() >= ONLINE_PLAYERS && handshake.accepts_all(&socket.connected_peers());
+    drop(socket);
+    if ready {
+        let level = match host_level(&players, &handshake, &ruleset, &levels, &selection) {
+            Ok(level) => level,
+            Err(reason) => {
+                warn!("Not starting the session: {reason}");
+                handshake.refused = Some(reason);
+                return;
+            }
+        };
         // take the socket
         let socket = session.socket.take().unwrap();
-        create_ggrs_session(commands, socket, lobby_id);
+        create_ggrs_session(
+            commands,
+            socket,
+            lobby_id,
+            &ruleset,
+            level,
+            debug_config.network_conditions,
+        );
         state
             .set(AppState::RoundOnline)
             .expect("Could not change state.");
     }
 }
 
-pub fn cleanup(mut commands: Commands) {
+pub fn update_status_text(
+    handshake: Res<Handshake>,
+    mut query: Query<&mut Text, With<ConnectStatusText>>,
+) {
+    if !handshake.is_changed() {
+        return;
+    }
+    let status = match &handshake.refused {
+        Some(reason) => format!("Can't play with this opponent.\n{reason}"),
+        None => "Searching a match...".to_owned(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != status {
+            text.sections[0].value = status.clone();
+        }
+    }
+}
+
+pub fn cleanup(mut commands: Commands, session: Option<Res<Session>>) {
+    // Once GGRS took the socket, the online round keeps using it for out-of-band messages
+    let started = session.map_or(false, |session| session.socket.is_none());
+    if !started {
+        commands.remove_resource::<PeerSocket>();
+    }
     commands.remove_resource::<Session>();
+    commands.remove_resource::<Handshake>();
 }
 
 pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
@@ -76,7 +170,7 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
         ))
         .with_children(|parent| {
             // lobby id display
-            parent.spawn(TextBundle {
+            parent.spawn((TextBundle {
                 style: Style {
                     align_self: AlignSelf::Center,
                     justify_content: JustifyContent::Center,
@@ -91,7 +185,7 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                     },
                 ),
                 ..default()
-            });
+            }, ConnectStatusText));
 
             // back button
             parent
@@ -147,18 +241,61 @@ pub fn cleanup_ui(query: Query<Entity, With<MenuConnectUI>>, mut commands: Comma
     }
 }
 
-fn create_ggrs_session(mut commands: Commands, socket: WebRtcSocket, lobby_id: Option<Res<LobbyID>>) {
+/// The level the host picked, as long as this peer has it and it fits every seat of the match.
+fn host_level(
+    players: &[PlayerType<String>],
+    handshake: &Handshake,
+    ruleset: &Ruleset,
+    levels: &Levels,
+    selection: &LevelSelection,
+) -> Result<LevelSelection, String> {
+    // The first player hosts, every peer agrees on who that is
+    let level = match players.first().and_then(|host| handshake.level(host)) {
+        Some(level) if !level.is_empty() => LevelSelection::Identifier(level.to_owned()),
+        _ => {
+            warn!("The host didn't pick a level, playing the selected one");
+            selection.clone()
+        }
+    };
+    let seats = players.len() + ruleset.bots;
+    match levels.selected(&level) {
+        None => match &level {
+            LevelSelection::Identifier(identifier) => {
+                Err(format!("The host's level {identifier} isn't in your game."))
+            }
+            _ => Err("The selected level isn't in your game.".to_owned()),
+        },
+        Some(info) if !info.supports(seats) => {
+            Err(format!("The level {} doesn't fit {seats} players.", info.name))
+        }
+        Some(info) => {
+            info!("Playing the level {}", info.identifier);
+            Ok(level)
+        }
+    }
+}
+
+fn create_ggrs_session(
+    mut commands: Commands,
+    socket: SharedSocket,
+    lobby_id: Option<Res<LobbyID>>,
+    ruleset: &Ruleset,
+    level: LevelSelection,
+    network_conditions: Option<NetworkConditions>,
+) {
     // create a new ggrs session
     let mut session_build = SessionBuilder::<GGRSConfig>::new()
-        .with_num_players(MAX_PLAYERS)
+        .with_num_players(ONLINE_PLAYERS)
         .with_max_prediction_window(12)
-        .with_fps(60)
+        .with_fps(FPS)
         .expect("Invalid FPS")
         .with_input_delay(2);
 
     // add players
     let mut handles = Vec::new();
-    for (i, player_type) in socket.players().iter().enumerate() {
+    let players = socket.lock().players();
+    commands.insert_resource(level);
+    for (i, player_type) in players.iter().enumerate() {
         if *player_type == PlayerType::Local {
             handles.push(i);
         }
@@ -168,11 +305,27 @@ fn create_ggrs_session(mut commands: Commands, socket: WebRtcSocket, lobby_id: O
     }
 
     // start the GGRS session
-    let session = session_build
-        .start_p2p_session(socket)
-        .expect("Session could not be created.");
+    let session = match network_conditions {
+        Some(conditions) => {
+            warn!("Simulating network conditions: {conditions:?}");
+            session_build.start_p2p_session(SimulatedSocket::new(socket, conditions, FPS))
+        }
+        None => session_build.start_p2p_session(socket),
+    }
+    .expect("Session could not be created.");
     let lobby_id = lobby_id.map(|res| (*res).clone());
     info!("Connected to lobby Id: {:?}", lobby_id);
+
+    // Bots take the handles after the online players. Every peer runs them from the same seed,
+    // and the handshake made sure they agree on how many there are.
+    commands.insert_resource(BotPlayers {
+        handles: (ONLINE_PLAYERS..ONLINE_PLAYERS + ruleset.bots).collect(),
+        profile: BotProfile::default(),
+    });
+    let seed = fnv1a64(lobby_id.as_ref().map_or("", |id| id.0.as_str()).as_bytes());
+    commands.insert_resource(SessionSeed(seed));
+    commands.insert_resource(RoundRng::new(seed));
+
     commands.insert_resource(LocalHandles { handles , lobby_id });
     commands.insert_resource(SessionType::P2PSession(session));
 }
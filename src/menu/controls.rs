@@ -0,0 +1,268 @@
+use crate::{
+    chat::CHAT_KEY,
+    input::InputAction,
+    resources::{FontAssets, LocalKeymaps},
+    components::online::AppState,
+    settings::save_keymaps,
+};
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct MenuControlsUI;
+
+#[derive(Component)]
+pub enum MenuControlsBtn {
+    Rebind { player: usize, action: InputAction },
+    Reset,
+    Back,
+}
+
+/// Shows the keys of one action for one local player.
+#[derive(Component)]
+pub struct BindingText {
+    player: usize,
+    action: InputAction,
+}
+
+#[derive(Component)]
+pub struct ControlsStatusText;
+
+/// The binding waiting for a key press, and what to tell the player about it.
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    waiting: Option<(usize, InputAction)>,
+    message: String,
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>, keymaps: Res<LocalKeymaps>) {
+    commands.init_resource::<Rebinding>();
+
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect::all(Val::Px(0.)),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_content: AlignContent::Center,
+                    align_items: AlignItems::Center,
+                    align_self: AlignSelf::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            MenuControlsUI,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Px(8.)),
+                        ..default()
+                    },
+                    text: Text::from_section(
+                        "Click a binding, then press the new key",
+                        text_style(28., Color::BLACK),
+                    ),
+                    ..default()
+                },
+                ControlsStatusText,
+            ));
+
+            // one row per action, one column per keymap
+            for action in InputAction::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Auto),
+                                ..default()
+                            },
+                            text: Text::from_section(action.label(), text_style(24., Color::BLACK)),
+                            ..default()
+                        });
+
+                        for player in 0..keymaps.0.len() {
+                            row.spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(200.0), Val::Px(36.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    margin: UiRect::all(Val::Px(4.)),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|button| {
+                                button.spawn((
+                                    TextBundle::from_section("", text_style(20., Color::CYAN)),
+                                    BindingText { player, action },
+                                ));
+                            })
+                            .insert(MenuControlsBtn::Rebind { player, action });
+                        }
+                    });
+            }
+
+            // the chat keys are fixed, so they only get a hint
+            parent.spawn(TextBundle {
+                style: Style {
+                    margin: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                text: Text::from_section(
+                    format!("{CHAT_KEY:?} opens the chat, Enter sends, 1-4 send emotes"),
+                    text_style(20., Color::BLACK),
+                ),
+                ..default()
+            });
+
+            for (label, btn) in [
+                ("Reset", MenuControlsBtn::Reset),
+                ("Back", MenuControlsBtn::Back),
+            ] {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Px(16.)),
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(label, text_style(40., Color::CYAN)),
+                            ..default()
+                        });
+                    })
+                    .insert(btn);
+            }
+        });
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<State<AppState>>,
+    mut keymaps: ResMut<LocalKeymaps>,
+    mut rebinding: ResMut<Rebinding>,
+    mut interaction_query: Query<(&Interaction, &MenuControlsBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Clicked = *interaction {
+            match btn {
+                MenuControlsBtn::Rebind { player, action } => {
+                    rebinding.waiting = Some((*player, *action));
+                    rebinding.message = format!(
+                        "Press a key for player {}'s {}, Esc to cancel",
+                        player + 1,
+                        action.label()
+                    );
+                }
+                MenuControlsBtn::Reset => {
+                    *keymaps = LocalKeymaps::default();
+                    save_keymaps(&keymaps);
+                    rebinding.waiting = None;
+                    rebinding.message = "Controls reset to the defaults".to_owned();
+                }
+                MenuControlsBtn::Back => {
+                    state
+                        .set(AppState::MenuMain)
+                        .expect("Could not change state.");
+                }
+            }
+        }
+    }
+}
+
+pub fn capture_key(
+    keys: Res<Input<KeyCode>>,
+    mut keymaps: ResMut<LocalKeymaps>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some((player, action)) = rebinding.waiting else {
+        return;
+    };
+    let Some(&key) = keys.get_just_pressed().next() else {
+        return;
+    };
+    rebinding.waiting = None;
+
+    if key == KeyCode::Escape {
+        rebinding.message = "Nothing changed".to_owned();
+        return;
+    }
+    if let Some(reserved) = LocalKeymaps::reserved(key) {
+        rebinding.message = format!("{key:?} {reserved}, pick another key");
+        return;
+    }
+    if let Some((other_player, other_action)) = keymaps.conflict(key, player, action) {
+        rebinding.message = format!(
+            "{key:?} is already player {}'s {}, pick another key",
+            other_player + 1,
+            other_action.label()
+        );
+        return;
+    }
+
+    keymaps.0[player].bind(action, key);
+    save_keymaps(&keymaps);
+    rebinding.message = format!(
+        "Player {}'s {} is now {key:?}",
+        player + 1,
+        action.label()
+    );
+}
+
+pub fn update_texts(
+    keymaps: Res<LocalKeymaps>,
+    rebinding: Res<Rebinding>,
+    mut binding_query: Query<(&mut Text, &BindingText), Without<ControlsStatusText>>,
+    mut status_query: Query<&mut Text, With<ControlsStatusText>>,
+    added_query: Query<(), Added<BindingText>>,
+) {
+    if keymaps.is_changed() || !added_query.is_empty() {
+        for (mut text, binding) in binding_query.iter_mut() {
+            let keys: Vec<String> = keymaps.0[binding.player]
+                .bindings(binding.action)
+                .iter()
+                .map(|key| format!("{key:?}"))
+                .collect();
+            text.sections[0].value = if keys.is_empty() {
+                "-".to_owned()
+            } else {
+                keys.join(" / ")
+            };
+        }
+    }
+
+    if rebinding.is_changed() && !rebinding.message.is_empty() {
+        for mut text in status_query.iter_mut() {
+            text.sections[0].value = rebinding.message.clone();
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuControlsUI>>, mut commands: Commands) {
+    commands.remove_resource::<Rebinding>();
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
@@ -0,0 +1,432 @@
+use crate::{
+    bot::{BotPlayers, BotProfile, BotProfiles},
+    constants::{FPS, MAX_PLAYERS},
+    input::InputAction,
+    plugins::levels_plugin::Levels,
+    resources::{
+        BotAssets, FontAssets, GamepadAssignments, LocalHandles, LocalKeymaps, LocalSeats, LobbyID,
+        RoundRng, Ruleset, SessionSeed,
+    },
+    components::{online::{AppState, GGRSConfig}},
+    sudden_death::{clock_text, next_round_seconds},
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LevelSelection;
+use bevy_ggrs::{
+    ggrs::{PlayerType, SessionBuilder},
+    Session as SessionType,
+};
+
+#[derive(Component)]
+pub struct MenuJoinUI;
+
+#[derive(Component)]
+pub enum MenuJoinBtn {
+    Humans,
+    Bots,
+    Difficulty,
+    RoundTime,
+    TimeUp,
+    Density,
+    Start,
+    Back,
+}
+
+/// Shows who controls the local player with this index.
+#[derive(Component)]
+pub struct JoinSlotText(usize);
+
+/// Label of the [`MenuJoinBtn::Humans`], [`MenuJoinBtn::Bots`], [`MenuJoinBtn::Difficulty`],
+/// [`MenuJoinBtn::RoundTime`], [`MenuJoinBtn::TimeUp`] or [`MenuJoinBtn::Density`] button.
+#[derive(Component)]
+pub struct SeatCountText;
+
+/// Names the selected level, and says so when it can't take this many players.
+#[derive(Component)]
+pub struct LevelNoteText;
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect::all(Val::Px(0.)),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_content: AlignContent::Center,
+                    align_items: AlignItems::Center,
+                    align_self: AlignSelf::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            MenuJoinUI,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Press A on a controller to join, B to leave",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 32.,
+                    color: Color::BLACK,
+                },
+            ));
+
+            // one line per local player
+            for index in 0..MAX_PLAYERS {
+                parent.spawn((
+                    TextBundle {
+                        style: Style {
+                            margin: UiRect::all(Val::Px(8.)),
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                font: font_assets.default_font.clone(),
+                                font_size: 32.,
+                                color: Color::BLACK,
+                            },
+                        ),
+                        ..default()
+                    },
+                    JoinSlotText(index),
+                ));
+            }
+
+            // seat counts side by side, then the round rules
+            let rows = [
+                vec![MenuJoinBtn::Humans, MenuJoinBtn::Bots, MenuJoinBtn::Difficulty],
+                vec![MenuJoinBtn::RoundTime, MenuJoinBtn::TimeUp, MenuJoinBtn::Density],
+            ];
+            for btns in rows {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for btn in btns {
+                            row.spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(260.0), Val::Px(50.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    margin: UiRect::all(Val::Px(8.)),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|button| {
+                                button.spawn((
+                                    TextBundle::from_section(
+                                        "",
+                                        TextStyle {
+                                            font: font_assets.default_font.clone(),
+                                            font_size: 32.0,
+                                            color: Color::CYAN,
+                                        },
+                                    ),
+                                    SeatCountText,
+                                ));
+                            })
+                            .insert(btn);
+                        }
+                    });
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 32.0,
+                        color: Color::BLACK,
+                    },
+                ),
+                LevelNoteText,
+            ));
+
+            for (label, btn) in [("Start", MenuJoinBtn::Start), ("Back", MenuJoinBtn::Back)] {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Px(16.)),
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                label,
+                                TextStyle {
+                                    font: font_assets.default_font.clone(),
+                                    font_size: 40.0,
+                                    color: Color::CYAN,
+                                },
+                            ),
+                            ..default()
+                        });
+                    })
+                    .insert(btn);
+            }
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn join_system(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut assignments: ResMut<GamepadAssignments>,
+    mut seats: ResMut<LocalSeats>,
+    bot_assets: Res<BotAssets>,
+    profiles: Res<Assets<BotProfiles>>,
+    levels: Res<Levels>,
+    selection: Res<LevelSelection>,
+) {
+    for gamepad in gamepads.iter() {
+        let just_pressed =
+            |button_type| buttons.just_pressed(GamepadButton::new(gamepad, button_type));
+
+        if just_pressed(GamepadButtonType::South) {
+            // joining takes a seat from the bots if needed
+            if let Some(index) = assignments.assign(gamepad) {
+                if index >= seats.humans {
+                    seats.humans = index + 1;
+                    seats.bots = seats.bots.min(MAX_PLAYERS - seats.humans);
+                }
+            }
+        }
+        if just_pressed(GamepadButtonType::East) {
+            assignments.unassign(gamepad);
+        }
+        if just_pressed(GamepadButtonType::Start) && level_fits(&seats, &levels, &selection) {
+            let profile = bot_profile(&seats, &bot_assets, &profiles);
+            start_local_round(&mut commands, &mut state, &seats, profile);
+            return;
+        }
+    }
+}
+
+pub fn update_seat_text(
+    seats: Res<LocalSeats>,
+    ruleset: Res<Ruleset>,
+    bot_assets: Res<BotAssets>,
+    profiles: Res<Assets<BotProfiles>>,
+    mut query: Query<(&mut Text, &Parent), With<SeatCountText>>,
+    btn_query: Query<&MenuJoinBtn>,
+    added_query: Query<(), Added<SeatCountText>>,
+) {
+    if !seats.is_changed() && !ruleset.is_changed() && added_query.is_empty() {
+        return;
+    }
+    for (mut text, parent) in query.iter_mut() {
+        text.sections[0].value = match btn_query.get(parent.get()) {
+            Ok(MenuJoinBtn::Humans) => format!("Humans: {}", seats.humans),
+            Ok(MenuJoinBtn::Bots) => format!("Bots: {}", seats.bots),
+            Ok(MenuJoinBtn::Difficulty) => {
+                format!("Bots play: {}", bot_profile(&seats, &bot_assets, &profiles).name)
+            }
+            Ok(MenuJoinBtn::RoundTime) => format!("Time: {}", clock_text(ruleset.round_seconds)),
+            Ok(MenuJoinBtn::TimeUp) => format!("Then: {}", ruleset.time_up.label()),
+            Ok(MenuJoinBtn::Density) => format!("Blocks: {}%", ruleset.arena.density),
+            _ => continue,
+        };
+    }
+}
+
+pub fn update_level_text(
+    seats: Res<LocalSeats>,
+    levels: Res<Levels>,
+    selection: Res<LevelSelection>,
+    mut query: Query<&mut Text, With<LevelNoteText>>,
+) {
+    let note = match levels.selected(&selection) {
+        Some(level) if !level.supports(seats.players()) => format!(
+            "{} needs {} to {} players",
+            level.name,
+            level.players.start(),
+            level.players.end()
+        ),
+        Some(level) => format!("Level: {}", level.name),
+        None => String::new(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != note {
+            text.sections[0].value = note.clone();
+        }
+    }
+}
+
+pub fn update_slot_text(
+    assignments: Res<GamepadAssignments>,
+    keymaps: Res<LocalKeymaps>,
+    seats: Res<LocalSeats>,
+    mut query: Query<(&mut Text, &JoinSlotText)>,
+    added_query: Query<(), Added<JoinSlotText>>,
+) {
+    if !assignments.is_changed() && !seats.is_changed() && added_query.is_empty() {
+        return;
+    }
+    for (mut text, slot) in query.iter_mut() {
+        if slot.0 >= seats.players() {
+            text.sections[0].value = format!("Player {}: -", slot.0 + 1);
+            continue;
+        }
+        if slot.0 >= seats.humans {
+            text.sections[0].value = format!("Player {}: bot", slot.0 + 1);
+            continue;
+        }
+
+        // e.g. "W A S D + Space"
+        let keys = keymaps.0.get(slot.0).map_or_else(String::new, |keymap| {
+            let first_key = |action| {
+                keymap
+                    .bindings(action)
+                    .first()
+                    .map_or_else(|| "-".to_owned(), |key| format!("{key:?}"))
+            };
+            format!(
+                "{} {} {} {} + {}",
+                first_key(InputAction::Up),
+                first_key(InputAction::Left),
+                first_key(InputAction::Down),
+                first_key(InputAction::Right),
+                first_key(InputAction::Fire)
+            )
+        });
+        let controls = match assignments.gamepad(slot.0) {
+            Some(gamepad) if keys.is_empty() => format!("controller {}", gamepad.id),
+            Some(gamepad) => format!("{keys} or controller {}", gamepad.id),
+            None if keys.is_empty() => "press A on a controller".to_owned(),
+            None => keys,
+        };
+        text.sections[0].value = format!("Player {}: {controls}", slot.0 + 1);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn btn_listeners(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut seats: ResMut<LocalSeats>,
+    mut ruleset: ResMut<Ruleset>,
+    bot_assets: Res<BotAssets>,
+    profiles: Res<Assets<BotProfiles>>,
+    levels: Res<Levels>,
+    selection: Res<LevelSelection>,
+    mut interaction_query: Query<(&Interaction, &MenuJoinBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Clicked = *interaction {
+            match btn {
+                // 1 to 4 humans, the bots fill what's left
+                MenuJoinBtn::Humans => {
+                    seats.humans = seats.humans % MAX_PLAYERS + 1;
+                    seats.bots = seats.bots.min(MAX_PLAYERS - seats.humans);
+                }
+                MenuJoinBtn::Bots => {
+                    seats.bots = (seats.bots + 1) % (MAX_PLAYERS - seats.humans + 1);
+                }
+                MenuJoinBtn::Difficulty => {
+                    let count = profiles
+                        .get(&bot_assets.profiles)
+                        .map_or(1, |profiles| profiles.profiles.len().max(1));
+                    seats.bot_profile = (seats.bot_profile + 1) % count;
+                }
+                MenuJoinBtn::RoundTime => ruleset.round_seconds = next_round_seconds(&ruleset),
+                MenuJoinBtn::TimeUp => ruleset.time_up = ruleset.time_up.next(),
+                MenuJoinBtn::Density => ruleset.arena.density = ruleset.arena.next_density(),
+                MenuJoinBtn::Start if level_fits(&seats, &levels, &selection) => {
+                    let profile = bot_profile(&seats, &bot_assets, &profiles);
+                    start_local_round(&mut commands, &mut state, &seats, profile);
+                }
+                MenuJoinBtn::Start => {}
+                // A pad may have started the round on this frame already
+                MenuJoinBtn::Back => {
+                    let _ = state.set(AppState::MenuMain);
+                }
+            }
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuJoinUI>>, mut commands: Commands) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn bot_profile(
+    seats: &LocalSeats,
+    bot_assets: &BotAssets,
+    profiles: &Assets<BotProfiles>,
+) -> BotProfile {
+    profiles
+        .get(&bot_assets.profiles)
+        .map(|profiles| profiles.get(seats.bot_profile))
+        .unwrap_or_default()
+}
+
+/// Whether the selected level has room for everyone, levels that aren't loaded yet are let through.
+fn level_fits(seats: &LocalSeats, levels: &Levels, selection: &LevelSelection) -> bool {
+    levels
+        .selected(selection)
+        .map_or(true, |level| level.supports(seats.players()))
+}
+
+fn start_local_round(
+    commands: &mut Commands,
+    state: &mut State<AppState>,
+    seats: &LocalSeats,
+    profile: BotProfile,
+) {
+    // The pad and the mouse can both start the round on the same frame, the first one wins
+    if state.set(AppState::RoundLocal).is_err() {
+        return;
+    }
+    create_synctest_session(commands, seats, profile);
+}
+
+fn create_synctest_session(commands: &mut Commands, seats: &LocalSeats, profile: BotProfile) {
+    // bots aren't GGRS players, they run inside the rollback schedule
+    let mut session_build = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(seats.humans)
+        .with_max_prediction_window(10)
+        .with_fps(FPS)
+        .expect("Invalid FPS")
+        .with_input_delay(2)
+        .with_check_distance(2);
+
+    for i in 0..seats.humans {
+        session_build = session_build
+            .add_player(PlayerType::Local, i)
+            .expect("Could not add local player");
+    }
+
+    let session = session_build.start_synctest_session().expect("");
+
+    commands.insert_resource(SessionType::SyncTestSession(session));
+    commands.insert_resource(LocalHandles {
+        handles: (0..seats.humans).collect(),
+        lobby_id: Some(LobbyID("local".to_owned()))
+    });
+    commands.insert_resource(BotPlayers {
+        handles: (seats.humans..seats.players()).collect(),
+        profile,
+    });
+    let seed = rand::random();
+    commands.insert_resource(SessionSeed(seed));
+    commands.insert_resource(RoundRng::new(seed));
+}
@@ -0,0 +1,167 @@
+use crate::{
+    components::online::AppState,
+    plugins::levels_plugin::Levels,
+    resources::FontAssets,
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LevelSelection;
+
+const CARD_COLOR: Color = Color::rgb(0.85, 0.85, 0.85);
+const SELECTED_CARD_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+
+#[derive(Component)]
+pub struct MenuLevelsUI;
+
+#[derive(Component)]
+pub enum MenuLevelsBtn {
+    Level(String),
+    Back,
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>, levels: Res<Levels>) {
+    let text_style = |font_size: f32| TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size,
+        color: Color::BLACK,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect::all(Val::Px(0.)),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_content: AlignContent::Center,
+                    align_items: AlignItems::Center,
+                    align_self: AlignSelf::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            MenuLevelsUI,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Pick a level", text_style(40.)));
+
+            // one card per level
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        flex_wrap: FlexWrap::Wrap,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|row| {
+                    for level in levels.0.iter() {
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::ColumnReverse,
+                                    align_items: AlignItems::Center,
+                                    margin: UiRect::all(Val::Px(16.)),
+                                    padding: UiRect::all(Val::Px(12.)),
+                                    ..default()
+                                },
+                                background_color: CARD_COLOR.into(),
+                                ..default()
+                            },
+                            MenuLevelsBtn::Level(level.identifier.clone()),
+                        ))
+                        .with_children(|card| {
+                            card.spawn(ImageBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(186.), Val::Px(150.)),
+                                    ..default()
+                                },
+                                image: level.thumbnail.clone().into(),
+                                ..default()
+                            });
+                            card.spawn(TextBundle::from_section(
+                                level.name.clone(),
+                                text_style(32.),
+                            ));
+                            let (min, max) = (level.players.start(), level.players.end());
+                            let players = if min == max {
+                                format!("{min} players")
+                            } else {
+                                format!("{min}-{max} players")
+                            };
+                            card.spawn(TextBundle::from_section(players, text_style(24.)));
+                        });
+                    }
+                });
+
+            // back button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Back to Menu", text_style(40.)));
+                })
+                .insert(MenuLevelsBtn::Back);
+        });
+}
+
+/// Highlights the card of the selected level.
+pub fn update_cards(
+    selection: Res<LevelSelection>,
+    levels: Res<Levels>,
+    mut card_query: Query<(&MenuLevelsBtn, &mut BackgroundColor)>,
+) {
+    let selected = levels.selected(&selection).map(|level| &level.identifier);
+    for (btn, mut color) in card_query.iter_mut() {
+        let MenuLevelsBtn::Level(identifier) = btn else {
+            continue;
+        };
+        let wanted = if selected == Some(identifier) {
+            SELECTED_CARD_COLOR
+        } else {
+            CARD_COLOR
+        };
+        if color.0 != wanted {
+            color.0 = wanted;
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<State<AppState>>,
+    mut selection: ResMut<LevelSelection>,
+    mut interaction_query: Query<(&Interaction, &MenuLevelsBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Clicked = *interaction {
+            match btn {
+                MenuLevelsBtn::Level(identifier) => {
+                    info!("Selected level {identifier}");
+                    *selection = LevelSelection::Identifier(identifier.clone());
+                }
+                MenuLevelsBtn::Back => {
+                    state
+                        .set(AppState::MenuMain)
+                        .expect("Could not change state.");
+                }
+            }
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuLevelsUI>>, mut commands: Commands) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
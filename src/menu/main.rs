@@ -1,13 +1,8 @@
 use crate::{
-    constants::MAX_PLAYERS,
-    resources::{FontAssets, LocalHandles, LobbyID},
-    components::{online::{AppState, GGRSConfig}},
+    resources::FontAssets,
+    components::online::AppState,
 };
 use bevy::prelude::*;
-use bevy_ggrs::{
-    ggrs::{PlayerType, SessionBuilder},
-    Session as SessionType,
-};
 
 #[derive(Component)]
 pub struct MenuMainUI;
@@ -15,7 +10,9 @@ pub struct MenuMainUI;
 #[derive(Component)]
 pub enum MenuMainBtn {
     OnlineMatch,
-    LocalMatch
+    LocalMatch,
+    Levels,
+    Controls,
 }
 
 pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
@@ -92,12 +89,67 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                     });
                 })
                 .insert(MenuMainBtn::LocalMatch);
+
+            // level select button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "Levels",
+                            TextStyle {
+                                font: font_assets.default_font.clone(),
+                                font_size: 40.0,
+                                color: Color::CYAN,
+                            },
+                        ),
+                        ..default()
+                    });
+                })
+                .insert(MenuMainBtn::Levels);
+
+            // controls button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "Controls",
+                            TextStyle {
+                                font: font_assets.default_font.clone(),
+                                font_size: 40.0,
+                                color: Color::CYAN,
+                            },
+                        ),
+                        ..default()
+                    });
+                })
+                .insert(MenuMainBtn::Controls);
         })
         .insert(MenuMainUI);
 }
 
 pub fn btn_listeners(
-    mut commands: Commands,
     mut state: ResMut<State<AppState>>,
     mut interaction_query: Query<(&Interaction, &MenuMainBtn), Changed<Interaction>>,
 ) {
@@ -110,9 +162,18 @@ pub fn btn_listeners(
                         .expect("Could not change state.");
                 }
                 MenuMainBtn::LocalMatch => {
-                    create_synctest_session(&mut commands);
                     state
-                        .set(AppState::RoundLocal)
+                        .set(AppState::MenuJoin)
+                        .expect("Could not change state.");
+                }
+                MenuMainBtn::Levels => {
+                    state
+                        .set(AppState::MenuLevels)
+                        .expect("Could not change state.");
+                }
+                MenuMainBtn::Controls => {
+                    state
+                        .set(AppState::MenuControls)
                         .expect("Could not change state.");
                 }
             }
@@ -125,27 +186,3 @@ pub fn cleanup_ui(query: Query<Entity, With<MenuMainUI>>, mut commands: Commands
         commands.entity(e).despawn_recursive();
     }
 }
-
-fn create_synctest_session(commands: &mut Commands) {
-    let mut session_build = SessionBuilder::<GGRSConfig>::new()
-        .with_num_players(MAX_PLAYERS)
-        .with_max_prediction_window(10)
-        .with_fps(60)
-        .expect("Invalid FPS")
-        .with_input_delay(2)
-        .with_check_distance(2);
-
-    for i in 0..2 {
-        session_build = session_build
-            .add_player(PlayerType::Local, i)
-            .expect("Could not add local player");
-    }
-
-    let session = session_build.start_synctest_session().expect("");
-
-    commands.insert_resource(SessionType::SyncTestSession(session));
-    commands.insert_resource(LocalHandles {
-        handles: (0..2).collect(),
-        lobby_id: Some(LobbyID("local".to_owned()))
-    });
-}
@@ -1,3 +1,6 @@
 pub mod connect;
+pub mod controls;
+pub mod join;
+pub mod levels;
 pub mod main;
 pub mod online;
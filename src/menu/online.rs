@@ -1,6 +1,8 @@
 use crate::{
-    resources::{ConnectData, FontAssets, LobbyID},
+    constants::{MAX_PLAYERS, ONLINE_PLAYERS},
+    resources::{ConnectData, FontAssets, LobbyID, Ruleset},
     components::{online::AppState},
+    sudden_death::{clock_text, next_round_seconds},
 };
 use bevy::prelude::*;
 
@@ -11,6 +13,10 @@ pub struct MenuOnlineUI;
 pub enum MenuOnlineBtn {
     LobbyMatch,
     QuickMatch,
+    Bots,
+    RoundTime,
+    TimeUp,
+    Density,
     Back,
 }
 
@@ -20,6 +26,14 @@ pub struct ButtonEnabled(bool);
 #[derive(Component)]
 pub struct LobbyCodeText;
 
+#[derive(Component)]
+pub struct BotCountText;
+
+/// Label of the [`MenuOnlineBtn::RoundTime`], [`MenuOnlineBtn::TimeUp`] or
+/// [`MenuOnlineBtn::Density`] button.
+#[derive(Component)]
+pub struct RoundRuleText;
+
 pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
     // lobby id resource
     commands.insert_resource(LobbyID("".to_owned()));
@@ -131,6 +145,70 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                 })
                 .insert(MenuOnlineBtn::QuickMatch);
 
+            // bot count button, part of the ruleset so both peers must pick the same
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                "",
+                                TextStyle {
+                                    font: font_assets.default_font.clone(),
+                                    font_size: 40.0,
+                                    color: Color::BLACK,
+                                },
+                            ),
+                            ..default()
+                        },
+                        BotCountText,
+                    ));
+                })
+                .insert(MenuOnlineBtn::Bots);
+
+            // round length and what happens after, part of the ruleset too
+            for btn in [MenuOnlineBtn::RoundTime, MenuOnlineBtn::TimeUp, MenuOnlineBtn::Density] {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::all(Val::Px(16.)),
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle {
+                                text: Text::from_section(
+                                    "",
+                                    TextStyle {
+                                        font: font_assets.default_font.clone(),
+                                        font_size: 32.0,
+                                        color: Color::BLACK,
+                                    },
+                                ),
+                                ..default()
+                            },
+                            RoundRuleText,
+                        ));
+                    })
+                    .insert(btn);
+            }
+
             // back button
             parent
                 .spawn(ButtonBundle {
@@ -206,9 +284,40 @@ pub fn update_lobby_btn(
     }
 }
 
+pub fn update_bot_count_text(
+    ruleset: Res<Ruleset>,
+    mut query: Query<&mut Text, With<BotCountText>>,
+) {
+    for mut text in query.iter_mut() {
+        let value = format!("Bots: {}", ruleset.bots);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+pub fn update_round_rule_text(
+    ruleset: Res<Ruleset>,
+    mut query: Query<(&mut Text, &Parent), With<RoundRuleText>>,
+    btn_query: Query<&MenuOnlineBtn>,
+) {
+    for (mut text, parent) in query.iter_mut() {
+        let value = match btn_query.get(parent.get()) {
+            Ok(MenuOnlineBtn::RoundTime) => format!("Time: {}", clock_text(ruleset.round_seconds)),
+            Ok(MenuOnlineBtn::TimeUp) => format!("Then: {}", ruleset.time_up.label()),
+            Ok(MenuOnlineBtn::Density) => format!("Blocks: {}%", ruleset.arena.density),
+            _ => continue,
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
 pub fn btn_listeners(
     mut commands: Commands,
     mut state: ResMut<State<AppState>>,
+    mut ruleset: ResMut<Ruleset>,
     lobby_id: Res<LobbyID>,
     mut interaction_query: Query<
         (&Interaction, &MenuOnlineBtn, Option<&ButtonEnabled>),
@@ -243,6 +352,12 @@ pub fn btn_listeners(
                         .set(AppState::MenuConnect)
                         .expect("Could not change state.");
                 }
+                MenuOnlineBtn::Bots => {
+                    ruleset.bots = (ruleset.bots + 1) % (MAX_PLAYERS - ONLINE_PLAYERS + 1);
+                }
+                MenuOnlineBtn::RoundTime => ruleset.round_seconds = next_round_seconds(&ruleset),
+                MenuOnlineBtn::TimeUp => ruleset.time_up = ruleset.time_up.next(),
+                MenuOnlineBtn::Density => ruleset.arena.density = ruleset.arena.next_density(),
                 MenuOnlineBtn::Back => {
                     state
                         .set(AppState::MenuMain)
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ggrs::ggrs::PlayerType;
+
+use crate::{
+    bomb::PowerUpTable, checksum::fnv1a64, plugins::ggrsp_plugin::rollback_signature,
+    resources::Ruleset,
+};
+
+use super::{MatchSocket, PeerPacket, RELIABLE_CHANNEL};
+
+const HANDSHAKE_MAGIC: &[u8; 4] = b"BBHS";
+
+/// What a peer has to match before we let it into a GGRS session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    pub game_version: String,
+    pub rollback_hash: u64,
+    /// Covers the [`Ruleset`] and the [`PowerUpTable`]
+    pub ruleset_hash: u64,
+    /// Identifier of the level the peer picked, played if it hosts. Doesn't have to match.
+    pub level: String,
+}
+
+impl ProtocolInfo {
+    pub fn local(ruleset: &Ruleset, power_ups: &PowerUpTable, level: &str) -> Self {
+        let mut rules = ruleset.to_bytes();
+        rules.extend_from_slice(&power_ups.to_bytes());
+        Self {
+            game_version: env!("CARGO_PKG_VERSION").to_owned(),
+            rollback_hash: fnv1a64(rollback_signature().join("\n").as_bytes()),
+            ruleset_hash: fnv1a64(&rules),
+            level: level.to_owned(),
+        }
+    }
+
+    /// Layout: magic, rollback hash, ruleset hash, level length and level, then the version
+    /// string.
+    pub fn encode(&self) -> Box<[u8]> {
+        // LDtk identifiers are short, longer ones are cut where a character ends so they decode
+        let mut level_len = self.level.len().min(u8::MAX as usize);
+        while !self.level.is_char_boundary(level_len) {
+            level_len -= 1;
+        }
+        let level = &self.level.as_bytes()[..level_len];
+        let mut packet = Vec::with_capacity(21 + level.len() + self.game_version.len());
+        packet.extend_from_slice(HANDSHAKE_MAGIC);
+        packet.extend_from_slice(&self.rollback_hash.to_le_bytes());
+        packet.extend_from_slice(&self.ruleset_hash.to_le_bytes());
+        packet.push(level.len() as u8);
+        packet.extend_from_slice(level);
+        packet.extend_from_slice(self.game_version.as_bytes());
+        packet.into_boxed_slice()
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let rest = packet.strip_prefix(HANDSHAKE_MAGIC)?;
+        if rest.len() < 17 {
+            return None;
+        }
+        let (rollback_hash, rest) = rest.split_at(8);
+        let (ruleset_hash, rest) = rest.split_at(8);
+        let (level_len, rest) = rest.split_at(1);
+        if rest.len() < level_len[0] as usize {
+            return None;
+        }
+        let (level, game_version) = rest.split_at(level_len[0] as usize);
+
+        Some(Self {
+            game_version: String::from_utf8(game_version.to_vec()).ok()?,
+            rollback_hash: u64::from_le_bytes(rollback_hash.try_into().ok()?),
+            ruleset_hash: u64::from_le_bytes(ruleset_hash.try_into().ok()?),
+            level: String::from_utf8(level.to_vec()).ok()?,
+        })
+    }
+
+    /// Explains why `remote` can't play with us, or `None` if it can.
+    pub fn mismatch(&self, remote: &ProtocolInfo) -> Option<String> {
+        if self.game_version != remote.game_version {
+            Some(format!(
+                "The other player runs version {}, you run {}.",
+                remote.game_version, self.game_version
+            ))
+        } else if self.rollback_hash != remote.rollback_hash {
+            Some("The other player's build simulates the game differently.".to_owned())
+        } else if self.ruleset_hash != remote.ruleset_hash {
+            Some("The other player uses different match rules.".to_owned())
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks the protocol exchange with every peer of the matchbox room.
+#[derive(Resource, Debug)]
+pub struct Handshake {
+    local: ProtocolInfo,
+    /// Peers whose protocol matches ours, with the level they picked
+    verified: HashMap<String, String>,
+    /// Why the match can't start, until another peer connects
+    pub refused: Option<String>,
+}
+
+impl Handshake {
+    pub fn new(local: ProtocolInfo) -> Self {
+        Self {
+            local,
+            verified: HashMap::new(),
+            refused: None,
+        }
+    }
+
+    /// Sends our protocol info to freshly connected peers and checks the info they sent back.
+    pub fn update<'a>(
+        &mut self,
+        socket: &mut dyn MatchSocket,
+        packets: impl Iterator<Item = &'a PeerPacket>,
+    ) {
+        for peer in socket.accept_new_connections() {
+            // A new opponent gets a fresh chance
+            self.refused = None;
+            info!("Sending protocol info to {peer}");
+            socket.send_on_channel(self.local.encode(), peer, RELIABLE_CHANNEL);
+        }
+
+        for PeerPacket { peer, packet } in packets {
+            // Other out-of-band messages share the channel
+            let Some(remote) = ProtocolInfo::decode(packet) else {
+                continue;
+            };
+            match self.local.mismatch(&remote) {
+                Some(reason) => {
+                    warn!("Refusing {peer}: {reason}");
+                    self.refused = Some(reason);
+                }
+                None => {
+                    info!("Protocol of {peer} matches ours");
+                    self.verified.insert(peer.clone(), remote.level);
+                }
+            }
+        }
+    }
+
+    /// True once every given peer sent a matching protocol info.
+    pub fn accepts_all(&self, peers: &[String]) -> bool {
+        self.refused.is_none() && peers.iter().all(|peer| self.verified.contains_key(peer))
+    }
+
+    /// The level picked by a verified peer, or by us.
+    pub fn level(&self, player: &PlayerType<String>) -> Option<&str> {
+        match player {
+            PlayerType::Remote(peer) => self.verified.get(peer).map(String::as_str),
+            _ => Some(&self.local.level),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(level: &str) -> ProtocolInfo {
+        ProtocolInfo {
+            game_version: "1.2.3".to_owned(),
+            rollback_hash: 0x0123_4567_89ab_cdef,
+            ruleset_hash: 42,
+            level: level.to_owned(),
+        }
+    }
+
+    #[test]
+    fn decode_reads_what_encode_wrote() {
+        let local = info("Level_3");
+        assert_eq!(ProtocolInfo::decode(&local.encode()), Some(local));
+        let unpicked = info("");
+        assert_eq!(ProtocolInfo::decode(&unpicked.encode()), Some(unpicked));
+    }
+
+    #[test]
+    fn encode_cuts_long_levels() {
+        let decoded = ProtocolInfo::decode(&info(&"a".repeat(300)).encode()).unwrap();
+        assert_eq!(decoded.level, "a".repeat(u8::MAX as usize));
+        assert_eq!(decoded.game_version, "1.2.3");
+    }
+
+    #[test]
+    fn encode_cuts_long_levels_between_characters() {
+        let decoded = ProtocolInfo::decode(&info(&"é".repeat(200)).encode()).unwrap();
+        assert_eq!(decoded.level, "é".repeat(127));
+    }
+
+    #[test]
+    fn decode_refuses_other_and_short_packets() {
+        let packet = info("Level_3").encode();
+        assert_eq!(ProtocolInfo::decode(b"CHAT hello"), None);
+        assert_eq!(ProtocolInfo::decode(&packet[..HANDSHAKE_MAGIC.len() + 16]), None);
+        // The level length says more than what's left
+        assert_eq!(ProtocolInfo::decode(&packet[..HANDSHAKE_MAGIC.len() + 20]), None);
+    }
+
+    #[test]
+    fn mismatch_names_the_first_difference() {
+        let local = info("Level_3");
+        assert_eq!(local.mismatch(&info("Level_1")), None);
+
+        let newer = ProtocolInfo {
+            game_version: "1.2.4".to_owned(),
+            ruleset_hash: 7,
+            ..info("Level_3")
+        };
+        assert!(local.mismatch(&newer).unwrap().contains("version 1.2.4"));
+        let other_rules = ProtocolInfo {
+            ruleset_hash: 7,
+            ..info("Level_3")
+        };
+        assert!(local.mismatch(&other_rules).unwrap().contains("match rules"));
+    }
+}
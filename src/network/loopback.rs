@@ -0,0 +1,167 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use bevy::prelude::*;
+use bevy_ggrs::ggrs::{Message, NonBlockingSocket, PlayerType};
+
+use super::MatchSocket;
+
+#[derive(Default)]
+struct Room {
+    peers: Vec<String>,
+    /// GGRS messages waiting for each receiver
+    ggrs_inboxes: HashMap<String, Vec<(String, Message)>>,
+    /// Out-of-band packets waiting for each (receiver, channel)
+    packet_inboxes: HashMap<(String, usize), Vec<(String, Box<[u8]>)>>,
+}
+
+#[derive(Default)]
+struct Rooms {
+    next_peer: usize,
+    rooms: HashMap<String, Room>,
+}
+
+/// An in-process stand-in for the matchbox signalling server and the WebRTC transport.
+///
+/// While this resource exists, the connect menu joins its rooms instead of the matchbox server.
+/// Apps sharing a clone of the same network, like two headless apps in a test, meet there
+/// and run the handshake and the GGRS session with no outside service.
+///
+/// It works inside one process only: two running copies of the game still need the matchbox
+/// server to find each other.
+#[derive(Resource, Clone, Default)]
+pub struct LoopbackNetwork(Arc<Mutex<Rooms>>);
+
+impl LoopbackNetwork {
+    fn lock(&self) -> MutexGuard<'_, Rooms> {
+        self.0.lock().expect("Loopback network lock poisoned")
+    }
+
+    /// Joins a room under a fresh peer id, as a matchbox client joining `room_id` would.
+    pub fn join(&self, room_id: &str) -> LoopbackSocket {
+        let mut rooms = self.lock();
+        rooms.next_peer += 1;
+        let id = format!("loopback-{}", rooms.next_peer);
+        rooms
+            .rooms
+            .entry(room_id.to_owned())
+            .or_default()
+            .peers
+            .push(id.clone());
+
+        LoopbackSocket {
+            id,
+            room_id: room_id.to_owned(),
+            known_peers: HashSet::new(),
+            network: self.clone(),
+        }
+    }
+}
+
+/// One peer of a [`LoopbackNetwork`] room. Messages are delivered instantly and never lost.
+pub struct LoopbackSocket {
+    id: String,
+    room_id: String,
+    known_peers: HashSet<String>,
+    network: LoopbackNetwork,
+}
+
+impl LoopbackSocket {
+    fn with_room<R>(&self, f: impl FnOnce(&mut Room) -> R) -> R {
+        let mut rooms = self.network.lock();
+        let room = rooms
+            .rooms
+            .get_mut(&self.room_id)
+            .expect("Loopback room closed while a socket is still in it");
+        f(room)
+    }
+}
+
+impl Drop for LoopbackSocket {
+    fn drop(&mut self) {
+        let mut rooms = self.network.lock();
+        let Some(room) = rooms.rooms.get_mut(&self.room_id) else {
+            return;
+        };
+        room.peers.retain(|peer| peer != &self.id);
+        room.ggrs_inboxes.remove(&self.id);
+        room.packet_inboxes.retain(|(peer, _), _| peer != &self.id);
+        if room.peers.is_empty() {
+            rooms.rooms.remove(&self.room_id);
+        }
+    }
+}
+
+impl NonBlockingSocket<String> for LoopbackSocket {
+    fn send_to(&mut self, msg: &Message, addr: &String) {
+        let from = self.id.clone();
+        self.with_room(|room| {
+            if room.peers.contains(addr) {
+                room.ggrs_inboxes
+                    .entry(addr.clone())
+                    .or_default()
+                    .push((from, msg.clone()));
+            }
+        });
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(String, Message)> {
+        self.with_room(|room| room.ggrs_inboxes.remove(&self.id).unwrap_or_default())
+    }
+}
+
+impl MatchSocket for LoopbackSocket {
+    fn accept_new_connections(&mut self) -> Vec<String> {
+        let new_peers: Vec<String> = self
+            .connected_peers()
+            .into_iter()
+            .filter(|peer| !self.known_peers.contains(peer))
+            .collect();
+        self.known_peers.extend(new_peers.iter().cloned());
+        new_peers
+    }
+
+    fn connected_peers(&self) -> Vec<String> {
+        self.with_room(|room| {
+            room.peers
+                .iter()
+                .filter(|peer| *peer != &self.id)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn players(&self) -> Vec<PlayerType<String>> {
+        // Sorted by id, like the matchbox socket, so every peer agrees on the handles
+        let mut ids = self.with_room(|room| room.peers.clone());
+        ids.sort();
+        ids.into_iter()
+            .map(|id| {
+                if id == self.id {
+                    PlayerType::Local
+                } else {
+                    PlayerType::Remote(id)
+                }
+            })
+            .collect()
+    }
+
+    fn send_on_channel(&mut self, packet: Box<[u8]>, peer: String, channel: usize) {
+        let from = self.id.clone();
+        self.with_room(|room| {
+            if room.peers.contains(&peer) {
+                room.packet_inboxes
+                    .entry((peer, channel))
+                    .or_default()
+                    .push((from, packet));
+            }
+        });
+    }
+
+    fn receive_on_channel(&mut self, channel: usize) -> Vec<(String, Box<[u8]>)> {
+        let key = (self.id.clone(), channel);
+        self.with_room(|room| room.packet_inboxes.remove(&key).unwrap_or_default())
+    }
+}
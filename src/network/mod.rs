@@ -0,0 +1,101 @@
+pub mod handshake;
+pub mod loopback;
+pub mod simulation;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bevy::prelude::*;
+use bevy_ggrs::ggrs::{Message, NonBlockingSocket, PlayerType};
+use matchbox_socket::WebRtcSocket;
+
+/// Reliable matchbox channel for everything sent outside of GGRS.
+/// The ggrs socket implementation always uses the first channel.
+pub const RELIABLE_CHANNEL: usize = 1;
+
+/// The parts of a matchbox socket the connect flow relies on, so it can run over other transports.
+pub trait MatchSocket: NonBlockingSocket<String> + Send {
+    /// Peers that connected since the last call.
+    fn accept_new_connections(&mut self) -> Vec<String>;
+    fn connected_peers(&self) -> Vec<String>;
+    /// Every player of the room, ordered the same way on each peer.
+    fn players(&self) -> Vec<PlayerType<String>>;
+    fn send_on_channel(&mut self, packet: Box<[u8]>, peer: String, channel: usize);
+    fn receive_on_channel(&mut self, channel: usize) -> Vec<(String, Box<[u8]>)>;
+}
+
+impl MatchSocket for WebRtcSocket {
+    fn accept_new_connections(&mut self) -> Vec<String> {
+        WebRtcSocket::accept_new_connections(self)
+    }
+
+    fn connected_peers(&self) -> Vec<String> {
+        WebRtcSocket::connected_peers(self)
+    }
+
+    fn players(&self) -> Vec<PlayerType<String>> {
+        WebRtcSocket::players(self)
+    }
+
+    fn send_on_channel(&mut self, packet: Box<[u8]>, peer: String, channel: usize) {
+        WebRtcSocket::send_on_channel(self, packet, peer, channel);
+    }
+
+    fn receive_on_channel(&mut self, channel: usize) -> Vec<(String, Box<[u8]>)> {
+        WebRtcSocket::receive_on_channel(self, channel)
+    }
+}
+
+/// A socket that the GGRS session and the out-of-band systems (handshake, chat) use together.
+///
+/// GGRS takes ownership of its socket when the session starts, so it gets a clone of this handle.
+#[derive(Clone)]
+pub struct SharedSocket(Arc<Mutex<Box<dyn MatchSocket>>>);
+
+impl SharedSocket {
+    pub fn new(socket: impl MatchSocket + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(socket))))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Box<dyn MatchSocket>> {
+        self.0.lock().expect("Socket lock poisoned")
+    }
+}
+
+impl NonBlockingSocket<String> for SharedSocket {
+    fn send_to(&mut self, msg: &Message, addr: &String) {
+        self.lock().send_to(msg, addr);
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(String, Message)> {
+        self.lock().receive_all_messages()
+    }
+}
+
+/// The socket used for out-of-band messages, from connecting until the online round ends.
+#[derive(Resource, Clone)]
+pub struct PeerSocket(pub SharedSocket);
+
+impl PeerSocket {
+    pub fn broadcast(&self, packet: &[u8]) {
+        let mut socket = self.0.lock();
+        for peer in socket.connected_peers() {
+            socket.send_on_channel(packet.into(), peer, RELIABLE_CHANNEL);
+        }
+    }
+}
+
+/// A packet received on [`RELIABLE_CHANNEL`]. Each consumer recognizes its own packets by their magic prefix.
+#[derive(Debug, Clone)]
+pub struct PeerPacket {
+    pub peer: String,
+    pub packet: Box<[u8]>,
+}
+
+pub fn receive_peer_packets(socket: Option<Res<PeerSocket>>, mut packets: EventWriter<PeerPacket>) {
+    let Some(socket) = socket else {
+        return;
+    };
+    for (peer, packet) in socket.0.lock().receive_on_channel(RELIABLE_CHANNEL) {
+        packets.send(PeerPacket { peer, packet });
+    }
+}
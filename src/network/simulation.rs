@@ -0,0 +1,91 @@
+use bevy_ggrs::ggrs::{Message, NonBlockingSocket};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::resources::NetworkConditions;
+
+struct InFlight {
+    deliver_at: u64,
+    from: String,
+    msg: Message,
+}
+
+/// Wraps a GGRS socket and degrades what it receives according to [`NetworkConditions`],
+/// to reproduce rollback-heavy matches on a single machine.
+///
+/// Only incoming messages are affected; give both peers the conditions to degrade both ways.
+///
+/// Delays are counted in polls rather than wall time. GGRS polls the socket once a frame, so the
+/// same seed and the same incoming traffic give the same losses and delays on every run.
+pub struct SimulatedSocket<S> {
+    inner: S,
+    conditions: NetworkConditions,
+    rng: StdRng,
+    /// Frames per second of the session, to turn milliseconds into polls
+    fps: usize,
+    polls: u64,
+    in_flight: Vec<InFlight>,
+}
+
+impl<S> SimulatedSocket<S> {
+    pub fn new(inner: S, conditions: NetworkConditions, fps: usize) -> Self {
+        Self {
+            inner,
+            conditions,
+            rng: StdRng::seed_from_u64(conditions.seed),
+            fps,
+            polls: 0,
+            in_flight: Vec::new(),
+        }
+    }
+
+    /// How many polls a message is held back for.
+    fn delay(&mut self) -> u64 {
+        let NetworkConditions {
+            latency_ms,
+            jitter_ms,
+            reorder,
+            ..
+        } = self.conditions;
+        let mut delay_ms = latency_ms + self.rng.gen_range(0..=jitter_ms);
+        if self.rng.gen_bool(reorder) {
+            // Long enough for a few later messages to overtake this one
+            delay_ms += latency_ms.max(50);
+        }
+        (delay_ms as u64 * self.fps as u64).div_ceil(1000)
+    }
+}
+
+impl<S: NonBlockingSocket<String>> NonBlockingSocket<String> for SimulatedSocket<S> {
+    fn send_to(&mut self, msg: &Message, addr: &String) {
+        self.inner.send_to(msg, addr);
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(String, Message)> {
+        self.polls += 1;
+        let now = self.polls;
+
+        for (from, msg) in self.inner.receive_all_messages() {
+            if self.rng.gen_bool(self.conditions.packet_loss) {
+                continue;
+            }
+            let deliver_at = now + self.delay();
+            self.in_flight.push(InFlight {
+                deliver_at,
+                from,
+                msg,
+            });
+        }
+
+        let (mut arrived, in_flight): (Vec<InFlight>, Vec<InFlight>) = self
+            .in_flight
+            .drain(..)
+            .partition(|message| message.deliver_at <= now);
+        self.in_flight = in_flight;
+        arrived.sort_by_key(|message| message.deliver_at);
+
+        arrived
+            .into_iter()
+            .map(|message| (message.from, message.msg))
+            .collect()
+    }
+}
@@ -1,34 +1,79 @@
-use std::ops::Range;
-
+use crate::arena::Arena;
 use crate::checksum::Checksum;
 use crate::components::collidable::ColliderBundle;
-use crate::components::game_elements::{Player, PlayerBundle};
+use crate::bot::BotPlayers;
+use crate::components::game_elements::{
+    Airborne, Bomb, BombBag, BotControl, Facing, LandMine, Player, PlayerBundle, Sliding,
+};
 use crate::components::online::{RoundEntity, GGRSConfig, };
-use crate::constants::MAX_PLAYERS;
-use crate::input::control_direction;
-use crate::resources::GameTextures;
+use crate::input::player_input;
+use crate::resources::{GameTextures, SessionSeed};
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::Worldly;
-use bevy_ggrs::ggrs::InputStatus;
 use bevy_ggrs::PlayerInputs;
-use bevy_ggrs::{Rollback, RollbackIdProvider};
+use bevy_ggrs::{Rollback, RollbackIdProvider, Session};
+
+/// How far a player walks in a frame, in world units.
+pub const MOVE_SPEED: f32 = 0.13;
 
 fn player_color(index: usize) -> Color {
     match index {
         0 => Color::rgb(1., 0., 0.),
         1 => Color::rgb(0., 1., 0.),
+        2 => Color::rgb(0., 0.4, 1.),
+        3 => Color::rgb(1., 0.9, 0.),
         _ => Color::rgb(0.27, 0.27, 0.27),
     }
 }
 
+/// Gives back what a rollback leaves out of the entities it respawns: GGRS only restores the
+/// registered components, so a player killed after the frame it rolls back to returns without
+/// its sprite, and every entity returns without the parts that draw it.
+///
+/// Bombs, flames and power-ups get their sprites from [`add_sprites_system`].
+///
+/// [`add_sprites_system`]: crate::bomb::add_sprites_system
+pub fn restore_rollback_entities_system(
+    mut commands: Commands,
+    game_textures: Res<GameTextures>,
+    spatial_query: Query<Entity, (With<Rollback>, Without<GlobalTransform>)>,
+    player_query: Query<(Entity, &Player), Without<Sprite>>,
+) {
+    for entity in spatial_query.iter() {
+        commands
+            .entity(entity)
+            .insert((GlobalTransform::default(), VisibilityBundle::default()));
+    }
+    for (entity, player) in player_query.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                color: player_color(player.handle),
+                ..default()
+            },
+            game_textures.player.clone(),
+            Worldly::default(),
+            ColliderBundle::player(),
+        ));
+    }
+}
+
 pub fn spawn_players(
     mut commands: Commands,
     mut rip: ResMut<RollbackIdProvider>,
     game_texture: Res<GameTextures>,
+    session: Res<Session<GGRSConfig>>,
+    bots: Option<Res<BotPlayers>>,
+    seed: Res<SessionSeed>,
 ) {
-    const RANGE: Range<usize> = 0..MAX_PLAYERS;
-    for (handle, index) in RANGE.enumerate() {
+    let num_players = match &*session {
+        Session::SyncTestSession(session) => session.num_players(),
+        Session::P2PSession(session) => session.num_players(),
+        Session::SpectatorSession(session) => session.num_players(),
+    };
+    let bot_handles = bots.map_or_else(Vec::new, |bots| bots.handles.clone());
+    // The arena puts them on their spawns once the level is loaded
+    for handle in (0..num_players).chain(bot_handles.iter().copied()) {
         let player_bundle = PlayerBundle {
             player_sprite: SpriteBundle {
                 transform: Transform {
@@ -37,52 +82,120 @@ pub fn spawn_players(
                     ..default()
                 },
                 sprite: Sprite {
-                    color: player_color(index),
+                    color: player_color(handle),
                     ..default()
                 },
                 texture: game_texture.player.clone(),
                 ..default()
             },
             player: Player { handle },
+            facing: Facing::default(),
+            bomb_bag: BombBag::default(),
             check_sum: Checksum::default(),
             rollback: Rollback::new(rip.next_id()),
             round_entity: RoundEntity,
             worldly: Worldly::default(),
-            collider_bundle: ColliderBundle::default()
+            collider_bundle: ColliderBundle::player(),
         };
-        commands.spawn(player_bundle);
+        let mut player = commands.spawn(player_bundle);
+        if bot_handles.contains(&handle) {
+            player.insert(BotControl::new(seed.0, handle));
+        }
     }
 }
 
+/// Walks the players on the grid of the arena: they can't enter hard or soft blocks, nor cells
+/// with a bomb, and line up with the lane they walk along so they don't clip corners.
+/// Players with a kick send the bombs they walk into sliding, see [`slide_bombs_system`].
+///
+/// It only reads the arena and the bombs, so rollbacks replay it the same on every peer.
+///
+/// [`slide_bombs_system`]: crate::bomb::slide_bombs_system
 pub fn move_players(
-    inputs: Res<PlayerInputs<GGRSConfig>>,
-    mut player_query: Query<(&mut Transform, &Player), With<Player>>,
+    inputs: Option<Res<PlayerInputs<GGRSConfig>>>,
+    arena: Res<Arena>,
+    mut player_query: Query<(
+        &mut Transform,
+        &Player,
+        &BombBag,
+        &mut Facing,
+        Option<&BotControl>,
+    )>,
+    mut bomb_query: Query<
+        (&Transform, &mut Sliding, &Airborne),
+        (With<Bomb>, Without<Player>, Without<LandMine>),
+    >,
 ) {
-    for (mut transform, player) in player_query.iter_mut() {
-        let input = match inputs[player.handle].1 {
-            InputStatus::Confirmed => inputs[player.handle].0,
-            InputStatus::Predicted => inputs[player.handle].0,
-            InputStatus::Disconnected => 0, // disconnected players do nothing
-        };
+    if !arena.is_built() {
+        return;
+    }
+    // Players walk under bombs in the air and over land mines
+    let bomb_cells: Vec<IVec2> = bomb_query
+        .iter()
+        .filter(|(.., airborne)| airborne.is_grounded())
+        .map(|(transform, ..)| arena.cell_at(transform.translation.xy()))
+        .collect();
+    let mut kicked = Vec::new();
+
+    // Lowest handle first when two players kick the same bomb
+    let mut players: Vec<_> = player_query.iter_mut().collect();
+    players.sort_by_key(|(_, player, ..)| player.handle);
+
+    for (transform, player, bomb_bag, facing, bot) in players.iter_mut() {
+        let input = player_input(inputs.as_deref(), player.handle, *bot);
 
-        let direction = control_direction(&input);
+        let direction = input.direction();
         if direction == Vec2::ZERO {
             continue;
         }
-        let move_speed = 0.13;
-        let move_delta = direction * move_speed;
         //it should not move on diagonal directions
-        let move_delta = if move_delta.x.abs() > move_delta.y.abs() {
-            Vec2::new(move_delta.x, 0.)
+        let axis = if direction.x.abs() > direction.y.abs() {
+            IVec2::new(direction.x.signum() as i32, 0)
         } else {
-            Vec2::new(0., move_delta.y)
+            IVec2::new(0, direction.y.signum() as i32)
         };
+        if facing.direction != axis {
+            facing.direction = axis;
+        }
+
+        let mut position = transform.translation.xy();
+        let cell = arena.cell_at(position);
+        let center = arena.cell_center(cell);
+        let mut step = MOVE_SPEED;
+
+        // Line up with the lane first, then walk with what's left of the step
+        let across = (center - position) * axis.as_vec2().perp().abs();
+        let line_up = across.clamp_length_max(step);
+        position += line_up;
+        step -= line_up.length();
 
-        let old_pos = transform.translation.xy();
-        let limit = Vec2::splat(5000. / 2. - 0.5);
-        let new_pos = (old_pos + move_delta).clamp(-limit, limit);
+        // Blocked cells stop the player at the center of their own cell. The bomb they just
+        // placed is on their own cell, so they can walk off of it.
+        let ahead = cell + axis;
+        let bomb_ahead = bomb_cells.contains(&ahead);
+        if !arena.is_floor(ahead) || bomb_ahead {
+            let to_center = (center - position).dot(axis.as_vec2());
+            step = step.min(to_center.max(0.));
+            // Walked into the bomb
+            if bomb_ahead && bomb_bag.kick && to_center <= MOVE_SPEED {
+                kicked.push((ahead, axis));
+            }
+        }
+        position += axis.as_vec2() * step;
+
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
 
-        transform.translation.x = new_pos.x;
-        transform.translation.y = new_pos.y;
+    for (transform, mut sliding, airborne) in bomb_query.iter_mut() {
+        if !airborne.is_grounded() {
+            continue;
+        }
+        let cell = arena.cell_at(transform.translation.xy());
+        if let Some((_, direction)) = kicked.iter().find(|(kicked, _)| *kicked == cell) {
+            if sliding.direction == IVec2::ZERO {
+                sliding.direction = *direction;
+            }
+        }
     }
 }
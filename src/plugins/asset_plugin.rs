@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::{LoadingStateAppExt, LoadingState};
 
-use crate::{components::online::AppState, resources::{FontAssets, GameTextures}};
+use crate::{components::online::AppState, resources::{BotAssets, FontAssets, GameTextures, LevelAssets}};
 
 pub struct AssetLoadingPlugin;
 
@@ -11,7 +11,9 @@ impl Plugin for AssetLoadingPlugin {
             LoadingState::new(AppState::AssetLoading)
                 .continue_to_state(AppState::MenuMain)
                 .with_collection::<FontAssets>()
-                .with_collection::<GameTextures>(),
+                .with_collection::<GameTextures>()
+                .with_collection::<LevelAssets>()
+                .with_collection::<BotAssets>(),
         );
     }
 }
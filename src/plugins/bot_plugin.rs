@@ -0,0 +1,13 @@
+use bevy::prelude::*;
+
+use crate::bot::{BotProfiles, BotProfilesLoader};
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        // the bots themselves run in the rollback schedule, see init_ggrsp_plugin
+        app.add_asset::<BotProfiles>()
+            .init_asset_loader::<BotProfilesLoader>();
+    }
+}
@@ -1,9 +1,15 @@
 use crate::{
+    chat::ChatInput,
+    network::loopback::LoopbackNetwork,
     components::game_elements::{Player},
-    resources::{CameraZoomConfig, DebugConfig, LobbyID, LocalHandles, WinSize},
+    resources::{CameraZoomConfig, DebugConfig, LobbyID, LocalHandles, NetworkConditions, WinSize},
 };
 use bevy::{input::mouse::MouseWheel, prelude::*, window::PresentMode};
 
+/// Debug hotkeys, see [`DebugConfig`].
+pub const VSYNC_KEY: KeyCode = KeyCode::V;
+pub const NETWORK_CONDITIONS_KEY: KeyCode = KeyCode::N;
+
 pub fn toggle_vsync(
     input: Res<Input<KeyCode>>,
     mut windows: ResMut<Windows>,
@@ -12,7 +18,7 @@ pub fn toggle_vsync(
     if !debug_config.enabled {
         return;
     }
-    if input.just_pressed(KeyCode::V) {
+    if input.just_pressed(VSYNC_KEY) {
         let window = windows.primary_mut();
 
         window.set_present_mode(if matches!(window.present_mode(), PresentMode::AutoVsync) {
@@ -24,6 +30,25 @@ pub fn toggle_vsync(
     }
 }
 
+pub fn toggle_network_conditions(
+    input: Res<Input<KeyCode>>,
+    chat_input: Res<ChatInput>,
+    mut debug_config: ResMut<DebugConfig>,
+) {
+    // N is just a letter while typing a message
+    if !debug_config.enabled || chat_input.open {
+        return;
+    }
+    if input.just_pressed(NETWORK_CONDITIONS_KEY) {
+        debug_config.network_conditions = match debug_config.network_conditions {
+            Some(_) => None,
+            None => Some(NetworkConditions::BAD),
+        };
+        // Sessions pick the conditions up when they start
+        info!("NETWORK_CONDITIONS: {:?}", debug_config.network_conditions);
+    }
+}
+
 pub fn init_window_plugin() -> WindowPlugin {
     WindowPlugin {
         window: WindowDescriptor {
@@ -36,6 +61,15 @@ pub fn init_window_plugin() -> WindowPlugin {
     }
 }
 
+/// Watches the asset folder while debugging, so saved levels reload in the running game.
+/// Browsers can't watch files.
+pub fn init_asset_plugin(debug_config: &DebugConfig) -> AssetPlugin {
+    AssetPlugin {
+        watch_for_changes: debug_config.enabled && cfg!(not(target_arch = "wasm32")),
+        ..default()
+    }
+}
+
 pub fn setup_window_system(mut commands: Commands, mut windows: ResMut<Windows>) {
     //Setup camera:
     let window = windows.get_primary_mut().unwrap();
@@ -133,14 +167,21 @@ pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
+        let debug_config = DebugConfig {
+            enabled: true, // Defina como 'false' ao construir uma versão de lançamento
+            network_conditions: None,
+            loopback: std::env::args().any(|arg| arg == "--loopback"),
+        };
+        if debug_config.enabled && debug_config.loopback {
+            app.insert_resource(LoopbackNetwork::default());
+        }
         app.add_plugins(
             DefaultPlugins
                 .set(init_window_plugin())
+                .set(init_asset_plugin(&debug_config))
                 .set(ImagePlugin::default_nearest()),
         )
-        .insert_resource(DebugConfig {
-            enabled: true, // Defina como 'false' ao construir uma versão de lançamento
-        })
+        .insert_resource(debug_config)
         .insert_resource(CameraZoomConfig {
             scroll_speed: 0.1,
             min_scale: 0.1,
@@ -149,6 +190,7 @@ impl Plugin for CameraPlugin {
         .add_startup_system(setup_window_system)
         .add_system(camera_follow_system)
         .add_system(camera_zoom_system)
-        .add_system(toggle_vsync);
+        .add_system(toggle_vsync)
+        .add_system(toggle_network_conditions);
     }
 }
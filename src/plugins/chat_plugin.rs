@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+
+use crate::{
+    chat::{self, ChatInput, ChatLogEntry, ChatMutes},
+    components::online::AppState,
+    network::{receive_peer_packets, PeerPacket},
+};
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatInput>()
+            .init_resource::<ChatMutes>()
+            .add_event::<PeerPacket>()
+            .add_event::<ChatLogEntry>()
+            .add_system_set(
+                SystemSet::on_exit(AppState::AssetLoading).with_system(chat::setup_ui),
+            )
+            .add_system_to_stage(CoreStage::PreUpdate, receive_peer_packets)
+            .add_system(chat::chat_keyboard_system)
+            .add_system(chat::receive_chat_system)
+            .add_system(chat::update_input_text)
+            .add_system(chat::spawn_log_lines)
+            .add_system(chat::fade_log_lines);
+    }
+}
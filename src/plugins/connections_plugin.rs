@@ -1,8 +1,13 @@
 use crate::{
+    arena::Arena,
+    bot::BotPlayers,
     components::online::{AppState, GGRSConfig, RoundEntity},
-    menu::{connect, online, main},
-    player::spawn_players,
-    resources::{LocalHandles, Session}, bomb::{explosion_animation_system, explosion_to_spawn_system},
+    menu::{connect, controls, join, levels, online, main},
+    network::PeerSocket,
+    player::{restore_rollback_entities_system, spawn_players},
+    resources::{LocalHandles, LocalSeats, RoundRng, Session, SessionSeed},
+    bomb::{add_sprites_system, explosion_animation_system, hide_land_mines_system},
+    sudden_death::RoundClock,
 };
 use bevy::prelude::*;
 use bevy_ggrs::Session as SessionType;
@@ -20,6 +25,7 @@ impl Plugin for ConnectMenuPlugin {
         .add_system_set(
             SystemSet::on_update(AppState::MenuConnect)
                 .with_system(connect::update_matchbox_socket)
+                .with_system(connect::update_status_text)
                 .with_system(connect::btn_listeners),
         )
         .add_system_set(
@@ -42,6 +48,8 @@ impl Plugin for OnlineMenuPlugin {
                 .with_system(online::update_lobby_id)
                 .with_system(online::update_lobby_id_display)
                 .with_system(online::update_lobby_btn)
+                .with_system(online::update_bot_count_text)
+                .with_system(online::update_round_rule_text)
                 .with_system(online::btn_listeners),
         )
         .add_system_set(
@@ -50,12 +58,75 @@ impl Plugin for OnlineMenuPlugin {
     }
 }
 
+pub struct JoinMenuPlugin;
+
+impl Plugin for JoinMenuPlugin {
+    fn build(&self, app: &mut App) {
+        // controller join screen before local rounds
+        app.init_resource::<LocalSeats>()
+            .add_system_set(SystemSet::on_enter(AppState::MenuJoin).with_system(join::setup_ui))
+            .add_system_set(
+                SystemSet::on_update(AppState::MenuJoin)
+                    .with_system(join::join_system)
+                    .with_system(join::update_seat_text)
+                    .with_system(join::update_level_text)
+                    .with_system(join::update_slot_text)
+                    .with_system(join::btn_listeners),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::MenuJoin).with_system(join::cleanup_ui));
+    }
+}
+
+pub struct ControlsMenuPlugin;
+
+impl Plugin for ControlsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        // key rebinding screen
+        app.add_system_set(
+            SystemSet::on_enter(AppState::MenuControls).with_system(controls::setup_ui),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::MenuControls)
+                .with_system(controls::btn_listeners)
+                .with_system(controls::capture_key)
+                .with_system(controls::update_texts),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::MenuControls).with_system(controls::cleanup_ui),
+        );
+    }
+}
+
+pub struct LevelsMenuPlugin;
+
+impl Plugin for LevelsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        // level select screen
+        app.add_system_set(SystemSet::on_enter(AppState::MenuLevels).with_system(levels::setup_ui))
+            .add_system_set(
+                SystemSet::on_update(AppState::MenuLevels)
+                    .with_system(levels::btn_listeners)
+                    .with_system(levels::update_cards),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::MenuLevels).with_system(levels::cleanup_ui),
+            );
+    }
+}
+
 pub struct LocalMatchPlugin;
 
 impl Plugin for LocalMatchPlugin {
     fn build(&self, app: &mut App) {
         // local round
         app.add_system_set(SystemSet::on_enter(AppState::RoundLocal).with_system(spawn_players))
+            .add_system_set(
+                SystemSet::on_update(AppState::RoundLocal)
+                    .with_system(restore_rollback_entities_system)
+                    .with_system(add_sprites_system)
+                    .with_system(explosion_animation_system)
+                    .with_system(hide_land_mines_system),
+            )
             .add_system_set(SystemSet::on_exit(AppState::RoundLocal).with_system(cleanup));
     }
 }
@@ -66,6 +137,14 @@ impl Plugin for OnlineMatchPlugin {
     fn build(&self, app: &mut App) {
         // online round
         app.add_system_set(SystemSet::on_enter(AppState::RoundOnline).with_system(spawn_players))
+            .add_system_set(
+                SystemSet::on_update(AppState::RoundOnline)
+                    .with_system(log_ggrs_events)
+                    .with_system(restore_rollback_entities_system)
+                    .with_system(add_sprites_system)
+                    .with_system(explosion_animation_system)
+                    .with_system(hide_land_mines_system),
+            )
             .add_system_set(SystemSet::on_exit(AppState::RoundOnline).with_system(cleanup));
     }
 }
@@ -75,11 +154,7 @@ pub struct MainMenuPlugin;
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
         // main menu
-        app.add_system_set(SystemSet::on_enter(AppState::MenuMain)
-            .with_system(main::setup_ui)
-            .with_system(explosion_animation_system)
-            .with_system(explosion_to_spawn_system)
-        )
+        app.add_system_set(SystemSet::on_enter(AppState::MenuMain).with_system(main::setup_ui))
         .add_system_set(
             SystemSet::on_update(AppState::MenuMain)
                 .with_system(main::btn_listeners),
@@ -91,9 +166,29 @@ impl Plugin for MainMenuPlugin {
 pub fn cleanup(query: Query<Entity, With<RoundEntity>>, mut commands: Commands) {
     commands.remove_resource::<LocalHandles>();
     commands.remove_resource::<Session>();
+    commands.remove_resource::<PeerSocket>();
     commands.remove_resource::<SessionType<GGRSConfig>>();
+    commands.remove_resource::<BotPlayers>();
+    // Rollback resources stay, the next round starts from empty ones
+    commands.insert_resource(Arena::default());
+    commands.insert_resource(RoundRng::default());
+    commands.insert_resource(RoundClock::default());
+    commands.insert_resource(SessionSeed::default());
 
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
 }
+
+/// Logs what GGRS reports during online rounds, like disconnects and desyncs
+/// when testing with simulated network conditions.
+pub fn log_ggrs_events(session: Option<ResMut<SessionType<GGRSConfig>>>) {
+    let Some(mut session) = session else {
+        return;
+    };
+    if let SessionType::P2PSession(session) = &mut *session {
+        for event in session.events() {
+            info!("GGRS event: {event:?}");
+        }
+    }
+}
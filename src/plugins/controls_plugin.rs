@@ -0,0 +1,22 @@
+use bevy::{input::InputSystem, prelude::*};
+
+use crate::{
+    input::gamepad_connection_system,
+    resources::GamepadAssignments,
+    settings::load_keymaps,
+    touch::{detect_touch_system, read_touch_buttons, touch_controls_visibility, TouchControls},
+};
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_keymaps())
+            .init_resource::<GamepadAssignments>()
+            .init_resource::<TouchControls>()
+            .add_system(gamepad_connection_system)
+            .add_system(detect_touch_system)
+            .add_system(touch_controls_visibility)
+            .add_system_to_stage(CoreStage::PreUpdate, read_touch_buttons.after(InputSystem));
+    }
+}
@@ -1,29 +1,177 @@
+use std::mem::size_of;
+
 use crate::{
+    arena::{build_arena_system, Arena},
+    bomb::{
+        bomb_explosion_system, flame_system, fly_bombs_system, glove_system, kill_players_system,
+        land_mine_system, pick_up_power_ups_system, player_place_bomb_system,
+        remote_control_system, slide_bombs_system, PowerUpTable,
+    },
+    bot::{bot_think_system, take_over_disconnected_system},
     checksum::{checksum_players_system, Checksum},
-    components::online::GGRSConfig,
-    input,
+    components::{
+        game_elements::{
+            Airborne, Bomb, BombBag, BotControl, Facing, Flame, FuseTime, LandMine, Player,
+            PowerUp, Sliding,
+        },
+        online::{GGRSConfig, RoundEntity},
+    },
+    constants::FPS,
+    input::{self, PlayerInput},
     player::move_players,
+    resources::{RoundRng, Ruleset, SessionSeed},
+    sudden_death::{round_clock_system, sudden_death_system, time_up_draw_system, RoundClock},
 };
 use bevy::prelude::*;
-use bevy_ggrs::GGRSPlugin;
+use bevy_ggrs::{ggrs::Config, GGRSPlugin};
+
+/// The components and resources GGRS saves and restores, with the names peers hash in
+/// [`rollback_signature`].
+///
+/// The names are written out since `type_name` may change with the compiler or a refactor, while
+/// both lists come from this one table so they can't drift apart.
+macro_rules! rollback_state {
+    (
+        components { $($component:ty => $component_name:literal,)* }
+        resources { $($resource:ty => $resource_name:literal,)* }
+    ) => {
+        fn register_rollback_state(plugin: GGRSPlugin<GGRSConfig>) -> GGRSPlugin<GGRSConfig> {
+            plugin
+                $(.register_rollback_component::<$component>())*
+                $(.register_rollback_resource::<$resource>())*
+        }
+
+        const ROLLBACK_STATE: &[&str] = &[
+            $(concat!("component:", $component_name),)*
+            $(concat!("resource:", $resource_name),)*
+        ];
+    };
+}
+
+rollback_state! {
+    components {
+        Transform => "transform",
+        Player => "player",
+        RoundEntity => "round_entity",
+        Checksum => "checksum",
+        Bomb => "bomb",
+        BombBag => "bomb_bag",
+        FuseTime => "fuse_time",
+        Sliding => "sliding",
+        Airborne => "airborne",
+        Facing => "facing",
+        LandMine => "land_mine",
+        Flame => "flame",
+        PowerUp => "power_up",
+        BotControl => "bot_control",
+    }
+    resources {
+        Arena => "arena",
+        RoundRng => "round_rng",
+        RoundClock => "round_clock",
+    }
+}
 
 pub fn init_ggrsp_plugin(app: &mut App) {
-    GGRSPlugin::<GGRSConfig>::new()
-        .with_update_frequency(60)
-        .with_input_system(input::handle_input_system)
-        .register_rollback_component::<Transform>()
-        .register_rollback_component::<Checksum>()
-        .with_rollback_schedule(
-            Schedule::default()
-                .with_stage(
-                    "ROLLBACK_STAGE",
-                    SystemStage::parallel().with_system(move_players),
+    register_rollback_state(
+        GGRSPlugin::<GGRSConfig>::new()
+            .with_update_frequency(FPS)
+            .with_input_system(input::handle_input_system),
+    )
+    .with_rollback_schedule(rollback_schedule())
+    .build(app);
+
+    app.init_resource::<Ruleset>()
+        .init_resource::<SessionSeed>()
+        .init_resource::<PowerUpTable>()
+        .init_resource::<Arena>()
+        .init_resource::<RoundRng>()
+        .init_resource::<RoundClock>();
+}
+
+/// The schedule GGRS runs for every frame, and rolls back.
+fn rollback_schedule() -> Schedule {
+    round_schedule(
+        SystemStage::parallel()
+            .with_system(build_arena_system)
+            .with_system(take_over_disconnected_system),
+    )
+    .with_stage_after(
+        "DAMAGE_STAGE",
+        "CHECKSUM_STAGE",
+        SystemStage::parallel().with_system(checksum_players_system),
+    )
+}
+
+/// The stages playing out a frame of a round, starting with `arena_stage`.
+///
+/// Headless tournaments run them without GGRS, see [`crate::tournament`].
+pub fn round_schedule(arena_stage: SystemStage) -> Schedule {
+    Schedule::default()
+        .with_stage("ARENA_STAGE", arena_stage)
+        .with_stage_after(
+            "ARENA_STAGE",
+            "BOT_STAGE",
+            SystemStage::parallel().with_system(bot_think_system),
+        )
+        .with_stage_after(
+            "BOT_STAGE",
+            "ROLLBACK_STAGE",
+            SystemStage::parallel()
+                .with_system(move_players)
+                // Bombs go on the cell the player walked to on this frame
+                .with_system(player_place_bomb_system.after(move_players))
+                // Kicks happen in move_players
+                .with_system(slide_bombs_system.after(player_place_bomb_system))
+                .with_system(glove_system.after(slide_bombs_system))
+                .with_system(fly_bombs_system.after(glove_system))
+                .with_system(remote_control_system.after(fly_bombs_system))
+                .with_system(land_mine_system.after(remote_control_system)),
+        )
+        .with_stage_after(
+            "ROLLBACK_STAGE",
+            "BOMB_STAGE",
+            SystemStage::parallel()
+                .with_system(bomb_explosion_system)
+                .with_system(flame_system)
+                .with_system(round_clock_system)
+                // Both change the arena, every peer has to apply them in the same order
+                .with_system(
+                    sudden_death_system
+                        .after(bomb_explosion_system)
+                        .after(round_clock_system),
                 )
-                .with_stage_after(
-                    "ROLLBACK_STAGE",
-                    "CHECKSUM_STAGE",
-                    SystemStage::parallel().with_system(checksum_players_system),
-                ),
+                .with_system(time_up_draw_system.after(round_clock_system)),
         )
-        .build(app);
+        .with_stage_after(
+            "BOMB_STAGE",
+            "DAMAGE_STAGE",
+            SystemStage::parallel()
+                .with_system(kill_players_system)
+                .with_system(pick_up_power_ups_system),
+        )
+}
+
+/// Describes the rollback setup registered in [`init_ggrsp_plugin`]: the input encoding,
+/// the rollback components and resources, and the systems of each stage.
+///
+/// Peers hash this during the connection handshake. The stages are read from the rollback
+/// schedule itself, each system by its function name.
+pub fn rollback_signature() -> Vec<String> {
+    type Input = <GGRSConfig as Config>::Input;
+
+    let mut signature = vec![format!("input:{}:v{}", size_of::<Input>(), PlayerInput::VERSION)];
+    signature.extend(ROLLBACK_STATE.iter().map(|state| state.to_string()));
+    for (label, stage) in rollback_schedule().iter_stages() {
+        let Some(stage) = stage.downcast_ref::<SystemStage>() else {
+            continue;
+        };
+        let systems: Vec<String> = stage
+            .parallel_systems()
+            .iter()
+            .map(|system| system.name().rsplit("::").next().unwrap_or_default().to_owned())
+            .collect();
+        signature.push(format!("stage:{}:{}", label.as_str(), systems.join(",")));
+    }
+    signature
 }
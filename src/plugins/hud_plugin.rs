@@ -0,0 +1,20 @@
+use bevy::prelude::*;
+
+use crate::{components::online::AppState, hud};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<hud::RoundLog>();
+        // the HUD is a round entity, the round cleanup takes it away
+        for state in [AppState::RoundLocal, AppState::RoundOnline] {
+            app.add_system_set(SystemSet::on_enter(state.clone()).with_system(hud::setup_hud))
+                .add_system_set(
+                    SystemSet::on_update(state)
+                        .with_system(hud::update_round_timer_text)
+                        .with_system(hud::log_round_system),
+                );
+        }
+    }
+}
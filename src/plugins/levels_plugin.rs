@@ -1,23 +1,151 @@
-use std::collections::{HashSet, HashMap};
+use std::{collections::{HashSet, HashMap}, ops::RangeInclusive};
 
 use crate::{
-    components::{online::AppState, collidable::{WallBundle, Wall}},
+    arena::{
+        is_generated, Arena, ArenaLayout, LevelRevision, Tile, COLLISION_LAYER, GENERATED_LEVEL,
+    },
+    components::{online::{AppState, RoundEntity}, collidable::{WallBundle, Wall}},
+    constants::{LEVEL_SCALE, MAX_PLAYERS},
+    resources::{GameTextures, LevelAssets},
 };
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{resources::DebugConfig, sudden_death::RoundClock};
 use bevy::ecs::entity::Entity;
 use bevy::prelude::*;
-use bevy_ecs_ldtk::prelude::*;
+use bevy::render::{
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::ImageSampler,
+};
+use bevy_ecs_ldtk::{
+    ldtk::{FieldValue, Level},
+    prelude::*,
+};
+use bevy_ecs_tilemap::prelude::{
+    TileBundle, TilePos, TileStorage, TileTextureIndex, TilemapBundle, TilemapId, TilemapSize,
+    TilemapTexture, TilemapTileSize,
+};
 use bevy_rapier2d::prelude::*;
 
-fn load_ldtk_levels(mut commands: Commands, assets: Res<AssetServer>) {
-    commands.spawn(LdtkWorldBundle {
-        ldtk_handle: assets.load("levels/Bomberboy.ldtk"),
-        transform: Transform {
-            translation: Vec3::new(0., 0., 11.),
-            scale: Vec3::new(0.16, 0.16, 1.),
+fn load_ldtk_levels(mut commands: Commands, level_assets: Res<LevelAssets>) {
+    commands.spawn((
+        LdtkWorldBundle {
+            ldtk_handle: level_assets.project.clone(),
+            transform: Transform {
+                translation: Vec3::new(0., 0., 11.),
+                scale: Vec3::new(LEVEL_SCALE, LEVEL_SCALE, 1.),
+                ..default()
+            },
             ..default()
         },
-        ..default()
-    });
+        // A new world is loaded for every round
+        RoundEntity,
+    ));
+}
+
+/// Represents a wide wall that is 1 tile tall
+/// Used to spawn wall collisions
+#[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+struct Plate {
+    left: i32,
+    right: i32,
+}
+
+/// A simple rectangle type representing a wall of any size
+///
+/// Each wall collider keeps the rectangle it covers, in cells, see [`update_wall_collision`].
+#[derive(Component, Clone, Copy, Debug)]
+struct WallRect {
+    left: i32,
+    right: i32,
+    top: i32,
+    bottom: i32,
+}
+
+/// Covers the walls of a level with as few rectangles as it can:
+/// 1. combine wall tiles into flat "plates" in each individual row
+/// 2. combine the plates into rectangles across multiple rows wherever possible
+fn wall_rects(walls: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<WallRect> {
+    // combine wall tiles into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+        for x in 0..width + 1 {
+            match (plate_start, walls.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rect_builder: HashMap<Plate, WallRect> = HashMap::new();
+    let mut prev_row: Vec<Plate> = Vec::new();
+    let mut wall_rects: Vec<WallRect> = Vec::new();
+
+    // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+    plate_stack.push(Vec::new());
+
+    for (y, current_row) in plate_stack.into_iter().enumerate() {
+        for prev_plate in &prev_row {
+            if !current_row.contains(prev_plate) {
+                // remove the finished rect so that the same plate in the future starts a new rect
+                if let Some(rect) = rect_builder.remove(prev_plate) {
+                    wall_rects.push(rect);
+                }
+            }
+        }
+        for plate in &current_row {
+            rect_builder
+                .entry(plate.clone())
+                .and_modify(|e| e.top += 1)
+                .or_insert(WallRect {
+                    bottom: y as i32,
+                    top: y as i32,
+                    left: plate.left,
+                    right: plate.right,
+                });
+        }
+        prev_row = current_row;
+    }
+    wall_rects
+}
+
+/// Spawns a collider for every rectangle, in the pixels of the level.
+///
+/// Making the colliders children of the level serves two purposes:
+/// 1. Adjusts the transforms to be relative to the level for free
+/// 2. the colliders will be despawned automatically when levels unload
+fn spawn_wall_colliders(level: &mut ChildBuilder, wall_rects: Vec<WallRect>, grid_size: i32) {
+    for wall_rect in wall_rects {
+        level
+            .spawn_empty()
+            .insert(Collider::cuboid(
+                (wall_rect.right as f32 - wall_rect.left as f32 + 1.) * grid_size as f32 / 2.,
+                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.) * grid_size as f32 / 2.,
+            ))
+            .insert(RigidBody::Fixed)
+            .insert(Friction::new(1.0))
+            .insert(Transform::from_xyz(
+                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32 / 2.,
+                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32 / 2.,
+                0.,
+            ))
+            .insert(GlobalTransform::default())
+            .insert(wall_rect);
+    }
 }
 
 /// Spawns heron collisions for the walls of a level
@@ -27,15 +155,8 @@ fn load_ldtk_levels(mut commands: Commands, assets: Res<AssetServer>) {
 /// This approach leads to bad performance.
 ///
 /// Instead, by flagging the wall tiles and spawning the collisions later,
-/// we can minimize the amount of colliding entities.
-///
-/// The algorithm used here is a nice compromise between simplicity, speed,
-/// and a small number of rectangle colliders.
-/// In basic terms, it will:
-/// 1. consider where the walls are
-/// 2. combine wall tiles into flat "plates" in each individual row
-/// 3. combine the plates into rectangles across multiple rows wherever possible
-/// 4. spawn colliders for each rectangle
+/// we can minimize the amount of colliding entities, see [`wall_rects`].
+/// Walls cleared later on are taken care of by [`update_wall_collision`].
 fn spawn_wall_collision(
     mut commands: Commands,
     wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
@@ -43,22 +164,6 @@ fn spawn_wall_collision(
     level_query: Query<(Entity, &Handle<LdtkLevel>)>,
     levels: Res<Assets<LdtkLevel>>,
 ) {
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn wall collisions
-    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
-    struct Plate {
-        left: i32,
-        right: i32,
-    }
-
-    /// A simple rectangle type representing a wall of any size
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
-    }
-
     // Consider where the walls are
     // storing them as GridCoords in a HashSet for quick, easy lookup
     //
@@ -87,125 +192,501 @@ fn spawn_wall_collision(
                     .get(level_handle)
                     .expect("Level should be loaded by this point");
 
-                let LayerInstance {
+                let Some(&LayerInstance {
                     c_wid: width,
                     c_hei: height,
                     grid_size,
                     ..
-                } = level
+                }) = level
                     .level
                     .layer_instances
-                    .clone()
-                    .expect("Level asset should have layers")[0];
-
-                // combine wall tiles into flat "plates" in each individual row
-                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-                for y in 0..height {
-                    let mut row_plates: Vec<Plate> = Vec::new();
-                    let mut plate_start = None;
-
-                    // + 1 to the width so the algorithm "terminates" plates that touch the right edge
-                    for x in 0..width + 1 {
-                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
-                            (Some(s), false) => {
-                                row_plates.push(Plate {
-                                    left: s,
-                                    right: x - 1,
-                                });
-                                plate_start = None;
-                            }
-                            (None, true) => plate_start = Some(x),
-                            _ => (),
-                        }
-                    }
-
-                    plate_stack.push(row_plates);
-                }
-
-                // combine "plates" into rectangles across multiple rows
-                let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
-                let mut prev_row: Vec<Plate> = Vec::new();
-                let mut wall_rects: Vec<Rect> = Vec::new();
-
-                // an extra empty row so the algorithm "finishes" the rects that touch the top edge
-                plate_stack.push(Vec::new());
-
-                for (y, current_row) in plate_stack.into_iter().enumerate() {
-                    for prev_plate in &prev_row {
-                        if !current_row.contains(prev_plate) {
-                            // remove the finished rect so that the same plate in the future starts a new rect
-                            if let Some(rect) = rect_builder.remove(prev_plate) {
-                                wall_rects.push(rect);
-                            }
-                        }
-                    }
-                    for plate in &current_row {
-                        rect_builder
-                            .entry(plate.clone())
-                            .and_modify(|e| e.top += 1)
-                            .or_insert(Rect {
-                                bottom: y as i32,
-                                top: y as i32,
-                                left: plate.left,
-                                right: plate.right,
-                            });
-                    }
-                    prev_row = current_row;
-                }
+                    .iter()
+                    .flatten()
+                    .find(|layer| layer.identifier == COLLISION_LAYER)
+                else {
+                    return;
+                };
 
+                let wall_rects = wall_rects(level_walls, width, height);
                 commands.entity(level_entity).with_children(|level| {
-                    // Spawn colliders for every rectangle..
-                    // Making the collider a child of the level serves two purposes:
-                    // 1. Adjusts the transforms to be relative to the level for free
-                    // 2. the colliders will be despawned automatically when levels unload
-                    for wall_rect in wall_rects {
-                        level
-                            .spawn_empty()
-                            .insert(Collider::cuboid(
-                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                            ))
-                            .insert(RigidBody::Fixed)
-                            .insert(Friction::new(1.0))
-                            .insert(Transform::from_xyz(
-                                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                0.,
-                            ))
-                            .insert(GlobalTransform::default());
-                    }
+                    spawn_wall_colliders(level, wall_rects, grid_size);
                 });
             }
         });
     }
 }
 
+/// Keeps the wall colliders in line with the [`Arena`], since explosions clear soft blocks and
+/// rollbacks bring them back.
+///
+/// Only the colliders covering a cleared cell are replaced, by colliders covering the rest of
+/// their rectangle. Restored cells get colliders of their own.
+///
+/// Rollbacks restore the arena every frame, so it's compared with the walls the colliders were
+/// last checked against before going through them.
+fn update_wall_collision(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut checked_walls: Local<HashSet<GridCoords>>,
+    collider_query: Query<(Entity, &WallRect, &Parent)>,
+    added_query: Query<(), Added<WallRect>>,
+) {
+    if !arena.is_built() || (!arena.is_changed() && added_query.is_empty()) {
+        return;
+    }
+    let walls: HashSet<GridCoords> = arena
+        .cells()
+        .filter(|cell| !arena.is_floor(*cell))
+        .map(|cell| GridCoords { x: cell.x, y: cell.y })
+        .collect();
+    if added_query.is_empty() && walls == *checked_walls {
+        return;
+    }
+
+    let mut level_to_covered: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
+    for (entity, rect, parent) in collider_query.iter() {
+        let cells: Vec<GridCoords> = (rect.bottom..=rect.top)
+            .flat_map(|y| (rect.left..=rect.right).map(move |x| GridCoords { x, y }))
+            .collect();
+        let covered = level_to_covered.entry(parent.get()).or_default();
+        covered.extend(cells.iter().copied());
+
+        if cells.iter().all(|cell| walls.contains(cell)) {
+            continue;
+        }
+        let remaining: HashSet<GridCoords> =
+            cells.into_iter().filter(|cell| walls.contains(cell)).collect();
+        let wall_rects = wall_rects(&remaining, arena.width, arena.height);
+        commands.entity(entity).despawn_recursive();
+        commands.entity(parent.get()).with_children(|level| {
+            spawn_wall_colliders(level, wall_rects, arena.grid_size);
+        });
+    }
+
+    for (level, covered) in level_to_covered.iter() {
+        let restored: HashSet<GridCoords> = walls.difference(covered).copied().collect();
+        if restored.is_empty() {
+            continue;
+        }
+        let wall_rects = wall_rects(&restored, arena.width, arena.height);
+        commands.entity(*level).with_children(|level| {
+            spawn_wall_colliders(level, wall_rects, arena.grid_size);
+        });
+    }
+    *checked_walls = walls;
+}
+
+/// The layer drawing the arena, and its tiles.
+const TILES_LAYER: &str = "Level_1_tiles";
+const PILLAR_TILE: u32 = 0;
+const FLOOR_TILE: u32 = 1;
+const BORDER_TILE: u32 = 2;
+const SOFT_BLOCK_TILE: u32 = 3;
+
+/// Marks the tilemap drawing the [`Arena`]: the tiles layer of an LDtk level, or the tilemap of
+/// a generated arena.
+#[derive(Component)]
+pub struct ArenaTiles;
+
+/// Root of a generated arena, with its tilemap and wall colliders as children.
+#[derive(Component)]
+pub struct GeneratedArena;
+
+fn tag_arena_tiles(
+    mut commands: Commands,
+    layer_query: Query<(Entity, &LayerMetadata), Added<LayerMetadata>>,
+) {
+    for (entity, layer) in layer_query.iter() {
+        if layer.identifier == TILES_LAYER {
+            commands.entity(entity).insert(ArenaTiles);
+        }
+    }
+}
+
+/// Draws the soft blocks of the [`Arena`], so blocks cleared by explosions disappear and come
+/// back if a rollback undoes the explosion. Blocks dropped by sudden death look like pillars.
+fn sync_arena_tiles(
+    arena: Res<Arena>,
+    layer_query: Query<(), With<ArenaTiles>>,
+    mut tile_query: Query<(&TilePos, &mut TileTextureIndex, &Parent)>,
+) {
+    if !arena.is_changed() || !arena.is_built() {
+        return;
+    }
+    for (tile_pos, mut texture_index, parent) in tile_query.iter_mut() {
+        if !layer_query.contains(parent.get()) {
+            continue;
+        }
+        let index = match arena.tile(IVec2::new(tile_pos.x as i32, tile_pos.y as i32)) {
+            // Pillars on the floor were dropped by a sudden death a rollback undid
+            Tile::Floor if matches!(texture_index.0, SOFT_BLOCK_TILE | PILLAR_TILE) => FLOOR_TILE,
+            Tile::Soft => SOFT_BLOCK_TILE,
+            Tile::Hard if matches!(texture_index.0, FLOOR_TILE | SOFT_BLOCK_TILE) => PILLAR_TILE,
+            _ => continue,
+        };
+        if texture_index.0 != index {
+            texture_index.0 = index;
+        }
+    }
+}
+
+/// Generated arenas have no LDtk level to draw them, so they get a tilemap of their own, with
+/// the tileset of the LDtk levels.
+fn spawn_generated_arena(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    level_selection: Res<LevelSelection>,
+    game_textures: Res<GameTextures>,
+    generated_query: Query<(), With<GeneratedArena>>,
+) {
+    if !arena.is_built() || !is_generated(&level_selection) || !generated_query.is_empty() {
+        return;
+    }
+
+    let size = TilemapSize {
+        x: arena.width as u32,
+        y: arena.height as u32,
+    };
+    let tile_size = TilemapTileSize {
+        x: arena.grid_size as f32,
+        y: arena.grid_size as f32,
+    };
+    let tilemap = commands.spawn_empty().id();
+    let mut storage = TileStorage::empty(size);
+    let (right, top) = (arena.width - 1, arena.height - 1);
+    for cell in arena.cells() {
+        let position = TilePos {
+            x: cell.x as u32,
+            y: cell.y as u32,
+        };
+        let index = match arena.tile(cell) {
+            Tile::Floor => FLOOR_TILE,
+            Tile::Soft => SOFT_BLOCK_TILE,
+            Tile::Hard if cell.x == 0 || cell.y == 0 || cell.x == right || cell.y == top => {
+                BORDER_TILE
+            }
+            Tile::Hard => PILLAR_TILE,
+        };
+        let tile = commands
+            .spawn(TileBundle {
+                position,
+                tilemap_id: TilemapId(tilemap),
+                texture_index: TileTextureIndex(index),
+                ..default()
+            })
+            .id();
+        commands.entity(tilemap).add_child(tile);
+        storage.set(&position, tile);
+    }
+    commands.entity(tilemap).insert((
+        TilemapBundle {
+            grid_size: tile_size.into(),
+            size,
+            storage,
+            texture: TilemapTexture::Single(game_textures.arena_tiles.clone()),
+            tile_size,
+            // Tiles are centered on their position, cells start at the corner
+            transform: Transform::from_xyz(tile_size.x / 2., tile_size.y / 2., 0.),
+            ..default()
+        },
+        ArenaTiles,
+    ));
+
+    // Same place and scale as the LDtk levels
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform {
+                translation: Vec3::new(0., 0., 11.),
+                scale: Vec3::new(LEVEL_SCALE, LEVEL_SCALE, 1.),
+                ..default()
+            }),
+            GeneratedArena,
+            RoundEntity,
+        ))
+        .add_child(tilemap);
+}
+
+/// The generated counterpart of [`spawn_wall_collision`], reading the walls from the arena.
+fn spawn_generated_wall_collision(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    generated_query: Query<Entity, Added<GeneratedArena>>,
+) {
+    for entity in generated_query.iter() {
+        // Soft blocks are walls too, like the LDtk levels register them
+        let walls: HashSet<GridCoords> = arena
+            .cells()
+            .filter(|cell| !arena.is_floor(*cell))
+            .map(|cell| GridCoords { x: cell.x, y: cell.y })
+            .collect();
+        let wall_rects = wall_rects(&walls, arena.width, arena.height);
+        commands.entity(entity).with_children(|level| {
+            spawn_wall_colliders(level, wall_rects, arena.grid_size);
+        });
+    }
+}
+
+/// The `MinPlayers` and `MaxPlayers` fields of a level, 2 and [`MAX_PLAYERS`] when missing.
+pub fn player_range(level: &Level) -> RangeInclusive<usize> {
+    let int_field = |identifier: &str, default: usize| {
+        level
+            .field_instances
+            .iter()
+            .find(|field| field.identifier == identifier)
+            .and_then(|field| match field.value {
+                FieldValue::Int(Some(value)) => usize::try_from(value).ok(),
+                _ => None,
+            })
+            .unwrap_or(default)
+    };
+    int_field("MinPlayers", 2)..=int_field("MaxPlayers", MAX_PLAYERS)
+}
+
+/// A level of the project, as the level select screen shows it.
+#[derive(Debug, Clone)]
+pub struct LevelInfo {
+    pub index: usize,
+    pub identifier: String,
+    /// The `Name` field of the level
+    pub name: String,
+    /// The `MinPlayers` and `MaxPlayers` fields of the level
+    pub players: RangeInclusive<usize>,
+    /// The arena, one pixel per cell
+    pub thumbnail: Handle<Image>,
+}
+
+impl LevelInfo {
+    fn new(index: usize, level: &Level, thumbnail: Handle<Image>) -> Self {
+        let name = level
+            .field_instances
+            .iter()
+            .find(|field| field.identifier == "Name")
+            .and_then(|field| match &field.value {
+                FieldValue::String(Some(name)) => Some(name.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| level.identifier.clone());
+
+        Self {
+            index,
+            identifier: level.identifier.clone(),
+            name,
+            players: player_range(level),
+            thumbnail,
+        }
+    }
+
+    pub fn is_selected(&self, selection: &LevelSelection) -> bool {
+        match selection {
+            LevelSelection::Identifier(identifier) => *identifier == self.identifier,
+            LevelSelection::Index(index) => *index == self.index,
+            _ => false,
+        }
+    }
+
+    pub fn supports(&self, players: usize) -> bool {
+        self.players.contains(&players)
+    }
+}
+
+/// The levels of the LDtk project, in the project's order.
+#[derive(Resource, Debug, Default)]
+pub struct Levels(pub Vec<LevelInfo>);
+
+impl Levels {
+    pub fn selected(&self, selection: &LevelSelection) -> Option<&LevelInfo> {
+        self.0.iter().find(|level| level.is_selected(selection))
+    }
+}
+
+/// Draws the arena of a level with the colors of its IntGrid values in LDtk.
+fn thumbnail(arena: &Arena) -> Image {
+    let mut data = Vec::with_capacity((arena.width * arena.height * 4) as usize);
+    // Images start with the top row, the arena with the bottom one
+    for y in (0..arena.height).rev() {
+        for x in 0..arena.width {
+            let color: [u8; 4] = match arena.tile(IVec2::new(x, y)) {
+                Tile::Floor => [0x28, 0x83, 0x04, 0xff],
+                Tile::Soft => [0x90, 0x53, 0x3a, 0xff],
+                Tile::Hard => [0x8b, 0x9b, 0xb4, 0xff],
+            };
+            data.extend_from_slice(&color);
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: arena.width as u32,
+            height: arena.height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // Keep the cells sharp when the thumbnail is scaled up
+    image.sampler_descriptor = ImageSampler::nearest();
+    image
+}
+
+/// Reads the levels of the project into [`Levels`] once it's loaded, and again when it changes.
+/// A generated arena comes last.
+fn catalog_levels(
+    mut events: EventReader<AssetEvent<LdtkAsset>>,
+    level_assets: Option<Res<LevelAssets>>,
+    projects: Res<Assets<LdtkAsset>>,
+    mut images: ResMut<Assets<Image>>,
+    mut levels: ResMut<Levels>,
+) {
+    let Some(level_assets) = level_assets else {
+        return;
+    };
+    let modified = events.iter().any(|event| {
+        matches!(event, AssetEvent::Modified { handle } if *handle == level_assets.project)
+    });
+    if !modified && !levels.0.is_empty() {
+        return;
+    }
+    let Some(project) = projects.get(&level_assets.project) else {
+        return;
+    };
+
+    for level in levels.0.drain(..) {
+        images.remove(&level.thumbnail);
+    }
+    for (index, level) in project.project.levels.iter().enumerate() {
+        let arena = match Arena::from_level(level) {
+            Ok(arena) => arena,
+            Err(err) => {
+                error!("Could not read level {}: {err}", level.identifier);
+                continue;
+            }
+        };
+        let thumbnail = images.add(thumbnail(&arena));
+        levels.0.push(LevelInfo::new(index, level, thumbnail));
+    }
+    // Every round on it is different, the thumbnail just gives an idea
+    levels.0.push(LevelInfo {
+        index: usize::MAX,
+        identifier: GENERATED_LEVEL.to_owned(),
+        name: "Random".to_owned(),
+        players: 2..=MAX_PLAYERS,
+        thumbnail: images.add(thumbnail(&Arena::generate(&ArenaLayout::default(), 0))),
+    });
+}
+
+/// Reads the level of a local round again when `Bomberboy.ldtk` is saved, for designers to try
+/// their changes without restarting.
+///
+/// `bevy_ecs_ldtk` respawns the level entities by itself, and [`spawn_wall_collision`] gives the
+/// new walls their colliders, in local rounds as in online ones.
+/// The [`LevelRevision`] makes [`build_arena_system`] read the arena again on the next frame and
+/// put the players back on their spawns.
+///
+/// Only the peer whose file changed would see the new level, so online rounds don't reload.
+///
+/// [`build_arena_system`]: crate::arena::build_arena_system
+#[cfg(not(target_arch = "wasm32"))]
+fn hot_reload_levels(
+    mut events: EventReader<AssetEvent<LdtkAsset>>,
+    debug_config: Res<DebugConfig>,
+    level_assets: Option<Res<LevelAssets>>,
+    state: Res<State<AppState>>,
+    clock: Res<RoundClock>,
+    mut revision: ResMut<LevelRevision>,
+) {
+    let Some(level_assets) = level_assets else {
+        return;
+    };
+    let modified = events.iter().any(|event| {
+        matches!(event, AssetEvent::Modified { handle } if *handle == level_assets.project)
+    });
+    if !modified || !debug_config.enabled {
+        return;
+    }
+    match state.current() {
+        AppState::RoundLocal => {
+            // Frames already played keep the old arena when SyncTest plays them again
+            revision.count += 1;
+            revision.frame = clock.frames + 1;
+            info!("Levels changed on disk, reloading the arena");
+        }
+        AppState::RoundOnline => warn!("Levels changed on disk, online rounds keep their arena"),
+        _ => (),
+    }
+}
+
 pub struct LevelsPlugin;
 impl Plugin for LevelsPlugin {
     fn build(&self, app: &mut App) {
         let level_set = |state: AppState| SystemSet::on_enter(state)
             .with_system(load_ldtk_levels);
+        let collision_set = |state: AppState| SystemSet::on_update(state)
+            .with_system(spawn_generated_arena)
+            .with_system(spawn_wall_collision)
+            .with_system(spawn_generated_wall_collision)
+            .with_system(update_wall_collision);
 
         app.add_plugin(LdtkPlugin)
             .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+            // Seen from above, nothing falls
             .insert_resource(RapierConfiguration {
-                gravity: Vec2::new(0.0, -2000.0),
+                gravity: Vec2::ZERO,
                 ..Default::default()
             })
             .insert_resource(LevelSelection::Index(0))
+            .init_resource::<Levels>()
+            .init_resource::<LevelRevision>()
             .register_ldtk_int_cell::<WallBundle>(1)
             .register_ldtk_int_cell::<WallBundle>(2)
-            .add_system_set(SystemSet::on_update(AppState::RoundOnline)
-                .with_system(spawn_wall_collision)
-            )
+            .add_system_set(collision_set(AppState::RoundLocal))
+            .add_system_set(collision_set(AppState::RoundOnline))
+            .add_system(tag_arena_tiles)
+            .add_system(sync_arena_tiles)
+            .add_system(catalog_levels)
             .add_system_set(level_set(AppState::RoundLocal))
             .add_system_set(level_set(AppState::RoundOnline));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system(hot_reload_levels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covered(rects: &[WallRect]) -> Vec<GridCoords> {
+        rects
+            .iter()
+            .flat_map(|rect| {
+                (rect.bottom..=rect.top)
+                    .flat_map(move |y| (rect.left..=rect.right).map(move |x| GridCoords { x, y }))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rects_cover_each_wall_once() {
+        let arena = Arena::generate(&ArenaLayout::default(), 4);
+        let walls: HashSet<GridCoords> = arena
+            .cells()
+            .filter(|cell| !arena.is_floor(*cell))
+            .map(|cell| GridCoords { x: cell.x, y: cell.y })
+            .collect();
+
+        let cells = covered(&wall_rects(&walls, arena.width, arena.height));
+        assert_eq!(cells.len(), walls.len());
+        assert_eq!(cells.into_iter().collect::<HashSet<_>>(), walls);
+    }
+
+    #[test]
+    fn stacked_plates_make_one_rect() {
+        let walls: HashSet<GridCoords> = (0..3)
+            .flat_map(|y| (1..4).map(move |x| GridCoords { x, y }))
+            .collect();
+        let rects = wall_rects(&walls, 5, 5);
+        assert_eq!(rects.len(), 1);
+        let rect = rects[0];
+        assert_eq!((rect.left, rect.right, rect.bottom, rect.top), (1, 3, 0, 2));
     }
 }
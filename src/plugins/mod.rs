@@ -2,4 +2,8 @@ pub mod connections_plugin;
 pub mod asset_plugin;
 pub mod ggrsp_plugin;
 pub mod camera_plugin;
-pub mod levels_plugin;
\ No newline at end of file
+pub mod chat_plugin;
+pub mod controls_plugin;
+pub mod levels_plugin;
+pub mod bot_plugin;
+pub mod hud_plugin;
@@ -1,12 +1,23 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::AssetCollection;
-use bevy_ecs_ldtk::prelude::LdtkEntity;
+use bevy_ecs_ldtk::prelude::{LdtkAsset, LdtkEntity};
 use bevy_ggrs::ggrs::PlayerHandle;
-use matchbox_socket::WebRtcSocket;
+
+use crate::{
+    arena::ArenaLayout,
+    bot::BotProfiles,
+    chat::{CHAT_KEY, EMOTES},
+    components::game_elements::BombKind,
+    constants::{FPS, MAX_PLAYERS, ONLINE_PLAYERS},
+    input::{InputAction, Keymap},
+    network::SharedSocket,
+    plugins::camera_plugin::{NETWORK_CONDITIONS_KEY, VSYNC_KEY},
+    sudden_death::TimeUp,
+};
 
 #[derive(Resource)]
 pub struct Session {
-    pub socket: Option<WebRtcSocket>,
+    pub socket: Option<SharedSocket>,
 }
 
 #[derive(Resource)]
@@ -21,8 +32,26 @@ pub struct GameTextures {
     pub player: Handle<Image>,
     #[asset(path = "textures/bomberman_bomb.png")]
     pub player_bomb: Handle<Image>,
+    #[asset(path = "textures/bomb_pierce.png")]
+    pub pierce_bomb: Handle<Image>,
+    #[asset(path = "textures/bomb_land_mine.png")]
+    pub land_mine: Handle<Image>,
     #[asset(path = "textures/explosion.png")]
-    pub explosion: Handle<TextureAtlas>
+    pub explosion: Handle<TextureAtlas>,
+    /// The tileset of the LDtk levels, also drawing generated arenas
+    #[asset(path = "textures/level_1_tiles.png")]
+    pub arena_tiles: Handle<Image>,
+}
+
+impl GameTextures {
+    /// The sprite of a bomb of this kind.
+    pub fn bomb(&self, kind: BombKind) -> Handle<Image> {
+        match kind {
+            BombKind::Normal => self.player_bomb.clone(),
+            BombKind::Pierce => self.pierce_bomb.clone(),
+            BombKind::LandMine => self.land_mine.clone(),
+        }
+    }
 }
 
 #[derive(Resource, Debug)]
@@ -39,15 +68,248 @@ pub struct FontAssets {
     pub default_font: Handle<Font>,
 }
 
+#[derive(AssetCollection, Resource)]
+pub struct LevelAssets {
+    #[asset(path = "levels/Bomberboy.ldtk")]
+    pub project: Handle<LdtkAsset>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct BotAssets {
+    #[asset(path = "bots/profiles.bots.ron")]
+    pub profiles: Handle<BotProfiles>,
+}
+
 #[derive(Resource, Debug)]
 pub struct LocalHandles {
     pub handles: Vec<PlayerHandle>,
     pub lobby_id: Option<LobbyID>
 }
 
+/// Keymaps of the players sharing the keyboard, in the order of their [`LocalHandles`].
+#[derive(Resource, Debug)]
+pub struct LocalKeymaps(pub Vec<Keymap>);
+
+impl Default for LocalKeymaps {
+    fn default() -> Self {
+        Self(vec![Keymap::left_hand(), Keymap::right_hand()])
+    }
+}
+
+impl LocalKeymaps {
+    /// What the game does with `key` outside the keymaps, if anything, so nobody binds it.
+    pub fn reserved(key: KeyCode) -> Option<&'static str> {
+        match key {
+            CHAT_KEY => Some("opens the chat"),
+            NETWORK_CONDITIONS_KEY => Some("toggles the network conditions"),
+            VSYNC_KEY => Some("toggles vsync"),
+            _ if EMOTES.iter().any(|(emote_key, _)| *emote_key == key) => Some("sends an emote"),
+            _ => None,
+        }
+    }
+
+    /// Finds who else already uses `key`, ignoring the binding of `action` for `player` itself.
+    pub fn conflict(
+        &self,
+        key: KeyCode,
+        player: usize,
+        action: InputAction,
+    ) -> Option<(usize, InputAction)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(other_player, keymap)| Some((other_player, keymap.action_of(key)?)))
+            .find(|conflict| *conflict != (player, action))
+    }
+}
+
+/// The controller of each local player, in the order of their [`LocalHandles`].
+#[derive(Resource, Debug)]
+pub struct GamepadAssignments {
+    pads: Vec<Option<Gamepad>>,
+}
+
+impl Default for GamepadAssignments {
+    fn default() -> Self {
+        Self {
+            pads: vec![None; MAX_PLAYERS],
+        }
+    }
+}
+
+impl GamepadAssignments {
+    pub fn gamepad(&self, index: usize) -> Option<Gamepad> {
+        self.pads.get(index).copied().flatten()
+    }
+
+    /// Gives the gamepad to the first player without one and returns that player's index.
+    /// Does nothing if the gamepad is already assigned or every player has one.
+    pub fn assign(&mut self, gamepad: Gamepad) -> Option<usize> {
+        if self.pads.contains(&Some(gamepad)) {
+            return None;
+        }
+        let index = self.pads.iter().position(Option::is_none)?;
+        self.pads[index] = Some(gamepad);
+        Some(index)
+    }
+
+    /// Frees the seat of the gamepad and returns its index, if it had one.
+    pub fn unassign(&mut self, gamepad: Gamepad) -> Option<usize> {
+        let index = self.pads.iter().position(|pad| *pad == Some(gamepad))?;
+        self.pads[index] = None;
+        Some(index)
+    }
+}
+
 #[derive(Resource, Debug)]
 pub struct DebugConfig {
     pub enabled: bool,
+    /// Degrades the GGRS traffic of online sessions started while it's set
+    pub network_conditions: Option<NetworkConditions>,
+    /// Connects online matches over a [`LoopbackNetwork`] instead of the matchbox server, to go
+    /// through the connect screens offline. Set by starting the game with `--loopback`.
+    ///
+    /// Only apps of this process can join its rooms, a second game instance can't.
+    ///
+    /// [`LoopbackNetwork`]: crate::network::loopback::LoopbackNetwork
+    pub loopback: bool,
+}
+
+/// Latency, jitter, loss and reordering applied to incoming GGRS messages, see [`SimulatedSocket`].
+///
+/// [`SimulatedSocket`]: crate::network::simulation::SimulatedSocket
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkConditions {
+    pub latency_ms: u32,
+    /// Each message gets up to this much extra delay, which also shuffles their order
+    pub jitter_ms: u32,
+    /// Chance, from 0 to 1, that a message is lost
+    pub packet_loss: f64,
+    /// Chance, from 0 to 1, that a message is held back behind the ones after it
+    pub reorder: f64,
+    /// Same seed and same incoming traffic, same losses and delays, so a bad run can be replayed
+    pub seed: u64,
+}
+
+impl NetworkConditions {
+    /// A congested mobile connection, enough to keep GGRS rolling back most frames.
+    pub const BAD: NetworkConditions = NetworkConditions {
+        latency_ms: 120,
+        jitter_ms: 60,
+        packet_loss: 0.1,
+        reorder: 0.05,
+        seed: 0,
+    };
+}
+
+/// Match rules every peer has to agree on, since they change the rollback simulation.
+#[derive(Resource, Debug, Clone)]
+pub struct Ruleset {
+    pub max_players: usize,
+    pub fps: usize,
+    /// Bots filling the seats after the players
+    pub bots: usize,
+    /// Used when the round is played on a generated arena
+    pub arena: ArenaLayout,
+    /// How long rounds play before time's up
+    pub round_seconds: u32,
+    pub time_up: TimeUp,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self {
+            max_players: ONLINE_PLAYERS,
+            fps: FPS,
+            bots: 0,
+            arena: ArenaLayout::default(),
+            round_seconds: 180,
+            time_up: TimeUp::SuddenDeath,
+        }
+    }
+}
+
+impl Ruleset {
+    /// Fixed-width encoding of the rules, so the same ruleset hashes the same on native and wasm.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(41);
+        bytes.extend_from_slice(&(self.max_players as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.fps as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.bots as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.arena.width.to_le_bytes());
+        bytes.extend_from_slice(&self.arena.height.to_le_bytes());
+        bytes.extend_from_slice(&self.arena.density.to_le_bytes());
+        bytes.extend_from_slice(&self.round_seconds.to_le_bytes());
+        bytes.push(self.time_up as u8);
+        bytes
+    }
+
+    /// How long rounds play before time's up, in rollback frames.
+    pub fn round_frames(&self) -> u32 {
+        self.round_seconds * self.fps as u32
+    }
+}
+
+/// Who fills the seats of a local round: humans take the first handles, bots the ones after.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LocalSeats {
+    pub humans: usize,
+    pub bots: usize,
+    /// Index of the bots' profile in [`BotProfiles`]
+    pub bot_profile: usize,
+}
+
+impl Default for LocalSeats {
+    fn default() -> Self {
+        Self {
+            humans: 2,
+            bots: 0,
+            bot_profile: 1,
+        }
+    }
+}
+
+impl LocalSeats {
+    pub fn players(&self) -> usize {
+        self.humans + self.bots
+    }
+}
+
+/// Seed of the current session, the same on every peer.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct SessionSeed(pub u64);
+
+/// Random numbers for the round simulation, like power-up drops.
+///
+/// It's part of the rollback state, so every peer draws the same numbers on the same frame.
+#[derive(Resource, Reflect, FromReflect, Default, Clone, Debug)]
+pub struct RoundRng {
+    state: u64,
+}
+
+impl RoundRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// SplitMix64, small and the same on every platform: <https://prng.di.unimi.it/splitmix64.c>
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (self.next_u64() % bound as u64) as u32
+    }
+
+    /// True `percent` times out of a hundred.
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent
+    }
 }
 
 #[derive(Resource, Debug)]
@@ -65,3 +327,44 @@ pub struct LDTKBundle {
     #[bundle]
     sprite_bundle: SpriteSheetBundle,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ruleset_bytes_have_a_fixed_layout() {
+        let bytes = Ruleset::default().to_bytes();
+        assert_eq!(bytes.len(), 41);
+        assert_eq!(bytes[..8], (ONLINE_PLAYERS as u64).to_le_bytes());
+        assert_eq!(bytes[8..16], (FPS as u64).to_le_bytes());
+        assert_eq!(bytes[36..40], 180u32.to_le_bytes());
+        assert_eq!(bytes[40], TimeUp::SuddenDeath as u8);
+    }
+
+    #[test]
+    fn every_rule_changes_the_bytes() {
+        let default = Ruleset::default().to_bytes();
+        let changed = [
+            Ruleset { bots: 1, ..default() },
+            Ruleset {
+                arena: ArenaLayout {
+                    density: 40,
+                    ..default()
+                },
+                ..default()
+            },
+            Ruleset {
+                round_seconds: 120,
+                ..default()
+            },
+            Ruleset {
+                time_up: TimeUp::Draw,
+                ..default()
+            },
+        ];
+        for ruleset in changed {
+            assert_ne!(ruleset.to_bytes(), default, "{ruleset:?}");
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::{input::Keymap, resources::LocalKeymaps};
+
+/// Name of the controls file on native, and of the localStorage entry in the browser.
+const CONTROLS_KEY: &str = "bomberboy_controls";
+
+/// Loads the saved keymaps over the defaults, keeping the default keys of players and actions
+/// that have none saved.
+pub fn load_keymaps() -> LocalKeymaps {
+    let mut keymaps = LocalKeymaps::default();
+    let Some(saved) = read_setting(CONTROLS_KEY) else {
+        return keymaps;
+    };
+    match ron::from_str::<Vec<Keymap>>(&saved) {
+        Ok(saved) => {
+            let taken: Vec<KeyCode> = saved.iter().flat_map(Keymap::keys).collect();
+            for (keymap, saved) in keymaps.0.iter_mut().zip(saved) {
+                keymap.merge(saved, &taken);
+            }
+        }
+        Err(err) => warn!("Ignoring the saved controls, they could not be read: {err}"),
+    }
+    keymaps
+}
+
+pub fn save_keymaps(keymaps: &LocalKeymaps) {
+    match ron::ser::to_string_pretty(&keymaps.0, PrettyConfig::default()) {
+        Ok(text) => write_setting(CONTROLS_KEY, &text),
+        Err(err) => warn!("Could not save the controls: {err}"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_setting(key: &str) -> Option<String> {
+    std::fs::read_to_string(format!("{key}.ron")).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_setting(key: &str, value: &str) {
+    if let Err(err) = std::fs::write(format!("{key}.ron"), value) {
+        warn!("Could not write {key}.ron: {err}");
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_setting(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_setting(key: &str, value: &str) {
+    let Some(storage) = local_storage() else {
+        warn!("localStorage is not available, {key} won't be saved");
+        return;
+    };
+    if storage.set_item(key, value).is_err() {
+        warn!("Could not write {key} to localStorage");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    arena::{Arena, Tile},
+    components::game_elements::{Bomb, Player, PowerUp},
+    constants::FPS,
+    resources::Ruleset,
+};
+use bevy::{math::Vec3Swizzles, prelude::*};
+use serde::Deserialize;
+
+/// Round lengths the menus cycle through, in seconds.
+pub const ROUND_SECONDS: [u32; 3] = [120, 180, 300];
+/// How long each block of sudden death takes to drop.
+pub const DROP_FRAMES: u32 = FPS as u32 / 6;
+
+/// What happens when the round clock runs out, see [`Ruleset::time_up`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TimeUp {
+    /// Hard blocks close the arena in, see [`sudden_death_system`]
+    #[default]
+    SuddenDeath,
+    /// The players left are out, see [`time_up_draw_system`]
+    Draw,
+}
+
+impl TimeUp {
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeUp::SuddenDeath => "sudden death",
+            TimeUp::Draw => "draw",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            TimeUp::SuddenDeath => TimeUp::Draw,
+            TimeUp::Draw => TimeUp::SuddenDeath,
+        }
+    }
+}
+
+/// The round length after the one of the ruleset in [`ROUND_SECONDS`].
+pub fn next_round_seconds(ruleset: &Ruleset) -> u32 {
+    let next = ROUND_SECONDS
+        .iter()
+        .position(|seconds| *seconds == ruleset.round_seconds)
+        .map_or(0, |index| (index + 1) % ROUND_SECONDS.len());
+    ROUND_SECONDS[next]
+}
+
+/// Frames played in the round, from the frame the arena was built.
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+pub struct RoundClock {
+    pub frames: u32,
+}
+
+impl RoundClock {
+    /// Frames left before time's up.
+    pub fn remaining(&self, ruleset: &Ruleset) -> u32 {
+        ruleset.round_frames().saturating_sub(self.frames)
+    }
+}
+
+/// Seconds as a clock shows them, like `2:05`.
+pub fn clock_text(seconds: u32) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// The cells inside the border of a `width` by `height` arena, from the outer ring inwards,
+/// clockwise from the top-left corner.
+///
+/// Arenas of LDtk levels take their size from the `c_wid` and `c_hei` of their `CollisionGrid`
+/// layer, like the wall colliders do.
+pub fn spiral(width: i32, height: i32) -> Vec<IVec2> {
+    let mut cells = Vec::new();
+    let (mut left, mut right, mut bottom, mut top) = (1, width - 2, 1, height - 2);
+    while left <= right && bottom <= top {
+        cells.extend((left..=right).map(|x| IVec2::new(x, top)));
+        cells.extend((bottom..top).rev().map(|y| IVec2::new(right, y)));
+        if bottom < top {
+            cells.extend((left..right).rev().map(|x| IVec2::new(x, bottom)));
+        }
+        if left < right {
+            cells.extend((bottom + 1..top).map(|y| IVec2::new(left, y)));
+        }
+        left += 1;
+        right -= 1;
+        bottom += 1;
+        top -= 1;
+    }
+    cells
+}
+
+pub fn round_clock_system(arena: Res<Arena>, mut clock: ResMut<RoundClock>) {
+    if arena.is_built() {
+        clock.frames += 1;
+    }
+}
+
+/// Once the round clock runs out, drops a hard block every [`DROP_FRAMES`] on the next cell of
+/// the [`spiral`] that isn't one already, crushing the players, bombs and power-ups on it.
+///
+/// The next cell only depends on the arena, so a rollback drops the same blocks again.
+pub fn sudden_death_system(
+    mut commands: Commands,
+    mut arena: ResMut<Arena>,
+    clock: Res<RoundClock>,
+    ruleset: Res<Ruleset>,
+    crushable_query: Query<(Entity, &Transform), Or<(With<Player>, With<Bomb>, With<PowerUp>)>>,
+) {
+    if ruleset.time_up != TimeUp::SuddenDeath {
+        return;
+    }
+    let Some(elapsed) = clock.frames.checked_sub(ruleset.round_frames()) else {
+        return;
+    };
+    if !arena.is_built() || elapsed % DROP_FRAMES != 0 {
+        return;
+    }
+    let Some(cell) = spiral(arena.width, arena.height)
+        .into_iter()
+        .find(|cell| arena.tile(*cell) != Tile::Hard)
+    else {
+        return;
+    };
+
+    arena.set_tile(cell, Tile::Hard);
+    for (entity, transform) in crushable_query.iter() {
+        if arena.cell_at(transform.translation.xy()) == cell {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Ends the round as a draw when the round clock runs out, by taking the players left out.
+pub fn time_up_draw_system(
+    mut commands: Commands,
+    clock: Res<RoundClock>,
+    ruleset: Res<Ruleset>,
+    player_query: Query<Entity, With<Player>>,
+) {
+    if ruleset.time_up != TimeUp::Draw || clock.frames != ruleset.round_frames() {
+        return;
+    }
+    for entity in player_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn spiral_covers_the_inside_once() {
+        for (width, height) in [(15, 13), (7, 7), (6, 9), (4, 3)] {
+            let cells = spiral(width, height);
+            let unique: HashSet<IVec2> = cells.iter().copied().collect();
+            assert_eq!(cells.len(), ((width - 2) * (height - 2)) as usize, "{width}x{height}");
+            assert_eq!(unique.len(), cells.len(), "{width}x{height}");
+            assert!(cells
+                .iter()
+                .all(|cell| (1..width - 1).contains(&cell.x) && (1..height - 1).contains(&cell.y)));
+        }
+    }
+
+    #[test]
+    fn spiral_walks_clockwise_from_the_top_left() {
+        let cells = spiral(5, 5);
+        let expected = [
+            (1, 3),
+            (2, 3),
+            (3, 3),
+            (3, 2),
+            (3, 1),
+            (2, 1),
+            (1, 1),
+            (1, 2),
+            (2, 2),
+        ];
+        assert_eq!(cells, expected.map(|(x, y)| IVec2::new(x, y)));
+    }
+
+    #[test]
+    fn each_step_is_next_to_the_last() {
+        let cells = spiral(15, 13);
+        for pair in cells.windows(2) {
+            let step = (pair[1] - pair[0]).abs();
+            assert_eq!(step.x + step.y, 1, "{} to {}", pair[0], pair[1]);
+        }
+    }
+}
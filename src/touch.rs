@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::online::AppState,
+    input::{InputAction, PlayerInput},
+    resources::FontAssets,
+};
+
+const DPAD_BUTTON_SIZE: f32 = 64.;
+const BOMB_BUTTON_SIZE: f32 = 112.;
+const BUTTON_COLOR: Color = Color::rgba(0.2, 0.2, 0.2, 0.4);
+
+/// On-screen controls for touch screens, shown during rounds once a touch was seen.
+#[derive(Resource, Debug, Default)]
+pub struct TouchControls {
+    pub enabled: bool,
+    /// What the fingers are pressing right now, merged into the first local player's input
+    pub input: PlayerInput,
+}
+
+#[derive(Component)]
+pub struct TouchControlsUI;
+
+#[derive(Component)]
+pub struct TouchButton(InputAction);
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    font_assets: &FontAssets,
+    action: InputAction,
+    label: &str,
+    size: f32,
+    position: UiRect,
+) {
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position,
+                    size: Size::new(Val::Px(size), Val::Px(size)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            TouchButton(action),
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 32.,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+fn setup_ui(commands: &mut Commands, font_assets: &FontAssets) {
+    let at = |left: f32, bottom: f32| UiRect {
+        left: Val::Px(left),
+        bottom: Val::Px(bottom),
+        ..default()
+    };
+
+    // d-pad in the bottom left corner
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: at(24., 24.),
+                    size: Size::new(
+                        Val::Px(DPAD_BUTTON_SIZE * 3.),
+                        Val::Px(DPAD_BUTTON_SIZE * 3.),
+                    ),
+                    ..default()
+                },
+                ..default()
+            },
+            TouchControlsUI,
+        ))
+        .with_children(|dpad| {
+            let step = DPAD_BUTTON_SIZE;
+            spawn_button(dpad, font_assets, InputAction::Up, "^", step, at(step, step * 2.));
+            spawn_button(dpad, font_assets, InputAction::Down, "v", step, at(step, 0.));
+            spawn_button(dpad, font_assets, InputAction::Left, "<", step, at(0., step));
+            spawn_button(dpad, font_assets, InputAction::Right, ">", step, at(step * 2., step));
+        });
+
+    // bomb button in the bottom right corner
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(32.),
+                        bottom: Val::Px(48.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(BOMB_BUTTON_SIZE), Val::Px(BOMB_BUTTON_SIZE)),
+                    ..default()
+                },
+                ..default()
+            },
+            TouchControlsUI,
+        ))
+        .with_children(|corner| {
+            spawn_button(
+                corner,
+                font_assets,
+                InputAction::Fire,
+                "BOMB",
+                BOMB_BUTTON_SIZE,
+                at(0., 0.),
+            );
+        });
+}
+
+/// Enables the touch controls the first time the screen is touched.
+pub fn detect_touch_system(
+    mut commands: Commands,
+    touches: Res<Touches>,
+    font_assets: Option<Res<FontAssets>>,
+    mut touch_controls: ResMut<TouchControls>,
+) {
+    if touch_controls.enabled || touches.iter().next().is_none() {
+        return;
+    }
+    // Fonts are needed for the labels, wait for the assets
+    let Some(font_assets) = font_assets else {
+        return;
+    };
+    info!("Touch input detected, showing the touch controls");
+    touch_controls.enabled = true;
+    setup_ui(&mut commands, &font_assets);
+}
+
+pub fn read_touch_buttons(
+    touches: Res<Touches>,
+    mut touch_controls: ResMut<TouchControls>,
+    button_query: Query<(&Node, &GlobalTransform, &ComputedVisibility, &TouchButton)>,
+) {
+    if !touch_controls.enabled {
+        return;
+    }
+
+    let mut input = PlayerInput::default();
+    // Every finger counts, so moving and placing a bomb work at the same time
+    for touch in touches.iter() {
+        let position = touch.position();
+        for (node, transform, visibility, button) in button_query.iter() {
+            if !visibility.is_visible() {
+                continue;
+            }
+            // Same hit test as bevy's ui focus system
+            let center = transform.translation().truncate();
+            let extents = node.size() / 2.;
+            let (min, max) = (center - extents, center + extents);
+            if (min.x..max.x).contains(&position.x) && (min.y..max.y).contains(&position.y) {
+                input.press(button.0);
+            }
+        }
+    }
+
+    if touch_controls.input != input {
+        touch_controls.input = input;
+    }
+}
+
+/// The controls only make sense while playing, the menus are tapped directly.
+pub fn touch_controls_visibility(
+    state: Res<State<AppState>>,
+    mut query: Query<&mut Visibility, With<TouchControlsUI>>,
+) {
+    let in_round = matches!(state.current(), AppState::RoundLocal | AppState::RoundOnline);
+    for mut visibility in query.iter_mut() {
+        if visibility.is_visible != in_round {
+            visibility.is_visible = in_round;
+        }
+    }
+}
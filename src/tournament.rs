@@ -0,0 +1,285 @@
+//! Headless bot-only rounds for balancing levels and power-up tables, started with
+//! `cargo run --release -- --tournament tournaments/balance.ron`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::LdtkJson;
+use bevy_ggrs::{ggrs::PlayerHandle, Rollback, RollbackIdProvider};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    arena::{Arena, ArenaLayout, GENERATED_LEVEL},
+    bomb::PowerUpTable,
+    bot::{BotPlayers, BotProfile, BotProfiles},
+    components::game_elements::{BombBag, BotControl, Facing, Player, PowerUp},
+    constants::{FPS, MAX_PLAYERS},
+    plugins::ggrsp_plugin::round_schedule,
+    resources::{RoundRng, Ruleset, SessionSeed},
+    sudden_death::{RoundClock, TimeUp},
+};
+
+const DEFAULT_CONFIG_PATH: &str = "tournaments/balance.ron";
+const PROJECT_PATH: &str = "assets/levels/Bomberboy.ldtk";
+const PROFILES_PATH: &str = "assets/bots/profiles.bots.ron";
+
+/// What a tournament plays: every combination of levels, player counts, bot profiles and
+/// power-up tables, for the same number of rounds each.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TournamentConfig {
+    /// Rounds played for each combination
+    pub rounds: usize,
+    /// Level identifiers, or `Generated`, every level of the project and a generated one if empty
+    pub levels: Vec<String>,
+    /// How many bots play each round
+    pub players: Vec<usize>,
+    /// Profile names from `assets/bots/profiles.bots.ron`, every profile if empty
+    pub profiles: Vec<String>,
+    /// Power-up tables to compare, with the names they're reported under
+    pub power_ups: Vec<(String, PowerUpTable)>,
+    /// Layout of the arenas played for the `Generated` level
+    pub arena: ArenaLayout,
+    /// How long rounds play before time's up
+    pub round_seconds: u32,
+    pub time_up: TimeUp,
+    /// Rounds still going after this long end as timeouts
+    pub max_seconds: u32,
+    /// Seed of the first round of each combination, the next rounds count up from it
+    pub seed: u64,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            rounds: 100,
+            levels: Vec::new(),
+            players: vec![2, MAX_PLAYERS],
+            profiles: Vec::new(),
+            power_ups: vec![("Default".to_owned(), PowerUpTable::default())],
+            arena: ArenaLayout::default(),
+            round_seconds: 180,
+            time_up: TimeUp::SuddenDeath,
+            max_seconds: 300,
+            seed: 0,
+        }
+    }
+}
+
+/// How the rounds of one combination went.
+#[derive(Debug, Default)]
+struct Results {
+    rounds: usize,
+    /// Wins by spawn position, which is the handle
+    wins: [usize; MAX_PLAYERS],
+    /// Everyone left died on the same frame
+    draws: usize,
+    timeouts: usize,
+    frames: u64,
+    dropped: HashMap<PowerUp, usize>,
+    picked: HashMap<PowerUp, usize>,
+}
+
+impl Results {
+    fn percent(&self, count: usize) -> f32 {
+        count as f32 * 100. / self.rounds.max(1) as f32
+    }
+
+    fn print(&self, players: usize) {
+        let wins: Vec<String> = (0..players)
+            .map(|handle| format!("{}: {:.1}%", handle + 1, self.percent(self.wins[handle])))
+            .collect();
+        println!(
+            "  wins by spawn  {}, draws {:.1}%, timeouts {:.1}%",
+            wins.join(", "),
+            self.percent(self.draws),
+            self.percent(self.timeouts)
+        );
+        println!(
+            "  average round  {:.1} s",
+            self.frames as f32 / self.rounds.max(1) as f32 / FPS as f32
+        );
+        for power_up in PowerUp::ALL {
+            let dropped = self.dropped.get(&power_up).copied().unwrap_or_default();
+            let picked = self.picked.get(&power_up).copied().unwrap_or_default();
+            println!(
+                "  {power_up:?}  dropped {:.2} and picked up {:.2} per round",
+                dropped as f32 / self.rounds.max(1) as f32,
+                picked as f32 / self.rounds.max(1) as f32
+            );
+        }
+    }
+}
+
+/// The config file given with `--tournament`, if the game was started with it.
+pub fn config_from_args() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--tournament" {
+            return Some(args.next().unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_owned()).into());
+        }
+    }
+    None
+}
+
+fn read_ron<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    ron::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Plays every round of the tournament and prints the results of each combination.
+pub fn run(config_path: &Path) -> Result<(), String> {
+    let config: TournamentConfig = read_ron(config_path)?;
+    let text = fs::read_to_string(PROJECT_PATH).map_err(|err| format!("{PROJECT_PATH}: {err}"))?;
+    let project: LdtkJson =
+        serde_json::from_str(&text).map_err(|err| format!("{PROJECT_PATH}: {err}"))?;
+    let profiles: BotProfiles = read_ron(Path::new(PROFILES_PATH))?;
+
+    let wanted = |identifier: &str| {
+        config.levels.is_empty() || config.levels.iter().any(|level| level == identifier)
+    };
+    // No arena stands for a generated one, different in each round
+    let mut levels = Vec::new();
+    for level in project.levels.iter().filter(|level| wanted(&level.identifier)) {
+        let arena = Arena::from_level(level)
+            .map_err(|err| format!("level {}: {err}", level.identifier))?;
+        levels.push((level.identifier.clone(), Some(arena)));
+    }
+    if wanted(GENERATED_LEVEL) {
+        levels.push((GENERATED_LEVEL.to_owned(), None));
+    }
+    let profiles: Vec<_> = profiles
+        .profiles
+        .into_iter()
+        .filter(|profile| config.profiles.is_empty() || config.profiles.contains(&profile.name))
+        .collect();
+    if levels.is_empty() || profiles.is_empty() {
+        return Err("no level or bot profile matches the config".to_owned());
+    }
+    if let Some(players) = config
+        .players
+        .iter()
+        .find(|players| !(2..=MAX_PLAYERS).contains(*players))
+    {
+        return Err(format!("rounds need 2 to {MAX_PLAYERS} players, not {players}"));
+    }
+
+    for (level, arena) in levels.iter() {
+        for players in config.players.iter() {
+            for profile in profiles.iter() {
+                for (table_name, table) in config.power_ups.iter() {
+                    let mut results = Results::default();
+                    for round in 0..config.rounds {
+                        let seed = config.seed.wrapping_add(round as u64);
+                        let arena = match arena {
+                            Some(arena) => arena.clone(),
+                            None => Arena::generate(&config.arena, seed),
+                        };
+                        let results = &mut results;
+                        play_round(&config, &arena, *players, profile, table, seed, results);
+                    }
+                    println!(
+                        "{level}, {players} {} bots, {table_name} power-ups, {} rounds",
+                        profile.name, results.rounds
+                    );
+                    results.print(*players);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the round stages of the rollback schedule on a world of its own, as fast as possible,
+/// until a single player is left.
+fn play_round(
+    config: &TournamentConfig,
+    arena: &Arena,
+    players: usize,
+    profile: &BotProfile,
+    power_ups: &PowerUpTable,
+    seed: u64,
+    results: &mut Results,
+) {
+    let max_frames = config.max_seconds as u64 * FPS as u64;
+    let mut world = World::new();
+    world.insert_resource(arena.clone());
+    world.insert_resource(RoundRng::new(seed));
+    world.insert_resource(SessionSeed(seed));
+    world.insert_resource(RoundClock::default());
+    world.insert_resource(Ruleset {
+        round_seconds: config.round_seconds,
+        time_up: config.time_up,
+        ..default()
+    });
+    world.insert_resource(power_ups.clone());
+    world.insert_resource(BotPlayers {
+        handles: (0..players).collect(),
+        profile: profile.clone(),
+    });
+
+    let mut rip = RollbackIdProvider::default();
+    let spawns = arena.spawn_cells();
+    for handle in 0..players {
+        let spawn = arena.cell_center(spawns[handle % spawns.len()]);
+        world.spawn((
+            Transform::from_xyz(spawn.x, spawn.y, 10.),
+            Player { handle },
+            Facing::default(),
+            BombBag::default(),
+            BotControl::new(seed, handle),
+            Rollback::new(rip.next_id()),
+        ));
+    }
+    world.insert_resource(rip);
+
+    // The arena is built above, there's nothing for the arena stage to do
+    let mut schedule = round_schedule(SystemStage::parallel());
+    let mut power_ups_on_field = HashMap::new();
+    results.rounds += 1;
+
+    for frame in 1..=max_frames {
+        schedule.run(&mut world);
+
+        let on_field: HashMap<Entity, (PowerUp, Vec3)> = world
+            .query::<(Entity, &PowerUp, &Transform)>()
+            .iter(&world)
+            .map(|(entity, power_up, transform)| (entity, (*power_up, transform.translation)))
+            .collect();
+        for (entity, (power_up, _)) in on_field.iter() {
+            if !power_ups_on_field.contains_key(entity) {
+                *results.dropped.entry(*power_up).or_default() += 1;
+            }
+        }
+        // Players take power-ups off the field, and so does sudden death when it drops a hard
+        // block on them. Picked up ones leave the floor they were on behind.
+        let arena = world.resource::<Arena>();
+        for (entity, (power_up, position)) in power_ups_on_field.iter() {
+            let crushed = !arena.is_floor(arena.cell_at(position.truncate()));
+            if !on_field.contains_key(entity) && !crushed {
+                *results.picked.entry(*power_up).or_default() += 1;
+            }
+        }
+        power_ups_on_field = on_field;
+
+        let alive: Vec<PlayerHandle> = world
+            .query::<&Player>()
+            .iter(&world)
+            .map(|player| player.handle)
+            .collect();
+        if alive.len() <= 1 {
+            results.frames += frame;
+            match alive.first() {
+                Some(handle) => results.wins[*handle] += 1,
+                None => results.draws += 1,
+            }
+            return;
+        }
+    }
+    results.frames += max_frames;
+    results.timeouts += 1;
+}
@@ -0,0 +1,282 @@
+//! Checks the levels of an LDtk project against the rules of the game, started with
+//! `cargo run -- --check-levels assets/levels/Bomberboy.ldtk`.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::IVec2;
+use bevy_ecs_ldtk::ldtk::{LdtkJson, Level};
+
+use crate::{
+    arena::{Arena, Tile, COLLISION_LAYER, DIRECTIONS},
+    bomb::blast_cells,
+    components::game_elements::BombBag,
+    plugins::levels_plugin::player_range,
+};
+
+const DEFAULT_PROJECT_PATH: &str = "assets/levels/Bomberboy.ldtk";
+
+/// The project given with `--check-levels`, if the game was started with it.
+pub fn project_from_args() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--check-levels" {
+            return Some(args.next().unwrap_or_else(|| DEFAULT_PROJECT_PATH.to_owned()).into());
+        }
+    }
+    None
+}
+
+/// Checks every level of the project and prints what's wrong with each.
+pub fn run(project_path: &Path) -> Result<(), String> {
+    let path = project_path.display();
+    let text = fs::read_to_string(project_path).map_err(|err| format!("{path}: {err}"))?;
+    let project: LdtkJson = serde_json::from_str(&text).map_err(|err| format!("{path}: {err}"))?;
+
+    let mut problems = check_layer_definition(&project);
+    for problem in problems.iter() {
+        println!("{path}: {problem}");
+    }
+    for level in project.levels.iter() {
+        let level_problems = check_level(level);
+        if level_problems.is_empty() {
+            println!("{}: ok", level.identifier);
+        }
+        for problem in level_problems.iter() {
+            println!("{}: {problem}", level.identifier);
+        }
+        problems.extend(level_problems);
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err("found 1 problem".to_owned()),
+        count => Err(format!("found {count} problems")),
+    }
+}
+
+/// The values declared for the `CollisionGrid` layer have to be block types the game knows.
+fn check_layer_definition(project: &LdtkJson) -> Vec<String> {
+    let Some(layer) = project
+        .defs
+        .layers
+        .iter()
+        .find(|layer| layer.identifier == COLLISION_LAYER)
+    else {
+        return vec![format!("no {COLLISION_LAYER} layer is defined")];
+    };
+    layer
+        .int_grid_values
+        .iter()
+        .filter(|value| Tile::from_int_grid(value.value).is_none())
+        .map(|value| {
+            format!(
+                "{COLLISION_LAYER} value {} ({}) isn't a block type, use 1 for breakable and 2 \
+                 for collidable",
+                value.value,
+                value.identifier.as_deref().unwrap_or("unnamed")
+            )
+        })
+        .collect()
+}
+
+fn check_level(level: &Level) -> Vec<String> {
+    let Some(layers) = level.layer_instances.as_ref() else {
+        return vec!["the game doesn't load layers saved in separate files".to_owned()];
+    };
+    let Some(layer) = layers.iter().find(|layer| layer.identifier == COLLISION_LAYER) else {
+        return vec![format!("no {COLLISION_LAYER} layer")];
+    };
+
+    let mut problems = Vec::new();
+    // Every unknown value, where Arena::from_layer stops at the first
+    for (i, value) in layer.int_grid_csv.iter().enumerate() {
+        if Tile::from_int_grid(*value).is_none() {
+            let (x, y) = (i as i32 % layer.c_wid, i as i32 / layer.c_wid);
+            problems.push(format!("unknown {COLLISION_LAYER} value {value} at ({x}, {y})"));
+        }
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+    let arena = match Arena::from_layer(layer) {
+        Ok(arena) => arena,
+        Err(err) => return vec![err],
+    };
+    if arena.width < 5 || arena.height < 5 {
+        return vec![format!(
+            "{}x{} cells is too small, the spawns need at least 5x5",
+            arena.width, arena.height
+        )];
+    }
+
+    let players = player_range(level);
+    let spawns = arena.spawn_cells();
+    if *players.start() < 2 || players.start() > players.end() {
+        problems.push(format!(
+            "MinPlayers {} and MaxPlayers {} don't make a range of 2 players or more",
+            players.start(),
+            players.end()
+        ));
+    }
+    if *players.end() > spawns.len() {
+        problems.push(format!(
+            "MaxPlayers is {} but the arena has {} spawns",
+            players.end(),
+            spawns.len()
+        ));
+    }
+
+    // The spawns used by the most players the level takes
+    let spawns = &spawns[..(*players.end()).clamp(1, spawns.len())];
+    for (handle, spawn) in spawns.iter().enumerate() {
+        let tile = arena.tile(*spawn);
+        if tile != Tile::Floor {
+            problems.push(format!(
+                "spawn {} at {} is on a {tile:?} block",
+                handle + 1,
+                ldtk_cell(&arena, *spawn)
+            ));
+        } else if boxed_in(&arena, *spawn) {
+            problems.push(format!(
+                "spawn {} at {} is boxed in, no bomb placed around it can be escaped",
+                handle + 1,
+                ldtk_cell(&arena, *spawn)
+            ));
+        }
+    }
+
+    // Soft blocks burn, hard ones stay
+    let reachable = flood(&arena, spawns[0], |tile| tile != Tile::Hard);
+    for (handle, spawn) in spawns.iter().enumerate().skip(1) {
+        if !reachable.contains(spawn) {
+            problems.push(format!(
+                "spawn {} at {} can't be reached from spawn 1, even with every soft block gone",
+                handle + 1,
+                ldtk_cell(&arena, *spawn)
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether the player can't open the way: every bomb they can place before clearing a soft
+/// block catches them wherever they can walk to.
+fn boxed_in(arena: &Arena, spawn: IVec2) -> bool {
+    let walkable = flood(arena, spawn, |tile| tile == Tile::Floor);
+    walkable.iter().all(|bomb| {
+        let blast = blast_cells(arena, *bomb, BombBag::default().range, false);
+        walkable.iter().all(|cell| blast.contains(cell))
+    })
+}
+
+/// The cells reachable from `start` through tiles that are `walkable`.
+fn flood(arena: &Arena, start: IVec2, walkable: impl Fn(Tile) -> bool) -> Vec<IVec2> {
+    let mut seen = vec![false; (arena.width * arena.height) as usize];
+    let mut reached = Vec::new();
+    let mut queue = VecDeque::from([start]);
+    seen[arena.index(start)] = true;
+    while let Some(cell) = queue.pop_front() {
+        reached.push(cell);
+        for direction in DIRECTIONS {
+            let next = cell + direction;
+            if arena.contains(next) && !seen[arena.index(next)] && walkable(arena.tile(next)) {
+                seen[arena.index(next)] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    reached
+}
+
+/// A cell as LDtk shows it, with rows counted from the top.
+fn ldtk_cell(arena: &Arena, cell: IVec2) -> String {
+    format!("({}, {})", cell.x, arena.height - 1 - cell.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_ldtk::ldtk::FieldValue;
+
+    use super::*;
+
+    /// The first level of the shipped project, which passes every check.
+    fn classic() -> Level {
+        let text = fs::read_to_string(DEFAULT_PROJECT_PATH).unwrap();
+        let project: LdtkJson = serde_json::from_str(&text).unwrap();
+        project.levels[0].clone()
+    }
+
+    /// Sets a cell of the `CollisionGrid` layer, in LDtk coordinates with rows from the top.
+    fn set(level: &mut Level, x: i32, y: i32, value: i32) {
+        let layer = level
+            .layer_instances
+            .as_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|layer| layer.identifier == COLLISION_LAYER)
+            .unwrap();
+        let width = layer.c_wid;
+        layer.int_grid_csv[(y * width + x) as usize] = value;
+    }
+
+    fn size(level: &Level) -> (i32, i32) {
+        let arena = Arena::from_level(level).unwrap();
+        (arena.width, arena.height)
+    }
+
+    #[test]
+    fn shipped_level_passes() {
+        assert_eq!(check_level(&classic()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_values_are_reported_where_they_are() {
+        let mut level = classic();
+        set(&mut level, 3, 1, 7);
+        assert_eq!(check_level(&level), vec!["unknown CollisionGrid value 7 at (3, 1)"]);
+    }
+
+    #[test]
+    fn spawns_must_be_on_floor_and_open() {
+        let mut level = classic();
+        set(&mut level, 1, 1, 2);
+        let problems = check_level(&level);
+        assert!(problems.iter().any(|problem| problem == "spawn 1 at (1, 1) is on a Hard block"));
+
+        let mut level = classic();
+        set(&mut level, 2, 1, 1);
+        set(&mut level, 1, 2, 1);
+        let problems = check_level(&level);
+        assert!(problems
+            .iter()
+            .any(|problem| problem.starts_with("spawn 1 at (1, 1) is boxed in")));
+    }
+
+    #[test]
+    fn walled_off_spawns_are_unreachable() {
+        let mut level = classic();
+        let (width, height) = size(&level);
+        // Spawn 2 is in the bottom-right corner
+        set(&mut level, width - 3, height - 2, 2);
+        set(&mut level, width - 2, height - 3, 2);
+        let problems = check_level(&level);
+        assert!(problems.iter().any(|problem| {
+            problem.starts_with("spawn 2") && problem.contains("can't be reached from spawn 1")
+        }));
+    }
+
+    #[test]
+    fn max_players_needs_a_spawn_each() {
+        let mut level = classic();
+        for field in level.field_instances.iter_mut() {
+            if field.identifier == "MaxPlayers" {
+                field.value = FieldValue::Int(Some(5));
+            }
+        }
+        assert_eq!(check_level(&level), vec!["MaxPlayers is 5 but the arena has 4 spawns"]);
+    }
+}
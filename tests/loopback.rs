@@ -0,0 +1,98 @@
+//! Two headless peers meeting on a [`LoopbackNetwork`], from the connect screen's handshake to
+//! a running GGRS session.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LevelSelection;
+use bevy_ggrs::Session as SessionType;
+use bomberboy::{
+    bomb::PowerUpTable,
+    components::online::{AppState, GGRSConfig},
+    menu::connect,
+    network::{loopback::LoopbackNetwork, receive_peer_packets, PeerPacket},
+    plugins::levels_plugin::{LevelInfo, Levels},
+    resources::{ConnectData, DebugConfig, Ruleset},
+};
+
+/// The connect screen's systems, without the UI, joining `network`.
+fn peer_app(network: &LoopbackNetwork, ruleset: Ruleset) -> App {
+    let mut app = App::new();
+    app.add_state(AppState::MenuConnect)
+        .add_event::<PeerPacket>()
+        .insert_resource(network.clone())
+        .insert_resource(ConnectData {
+            lobby_id: "loopback-test".to_owned(),
+        })
+        .insert_resource(DebugConfig {
+            enabled: false,
+            network_conditions: None,
+            loopback: true,
+        })
+        .insert_resource(ruleset)
+        .insert_resource(PowerUpTable::default())
+        .insert_resource(Levels(vec![LevelInfo {
+            index: 0,
+            identifier: "Level_0".to_owned(),
+            name: "Test".to_owned(),
+            players: 2..=4,
+            thumbnail: Handle::default(),
+        }]))
+        .insert_resource(LevelSelection::Index(0))
+        .add_system_to_stage(CoreStage::PreUpdate, receive_peer_packets)
+        .add_system_set(
+            SystemSet::on_enter(AppState::MenuConnect)
+                .with_system(connect::create_matchbox_socket),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::MenuConnect)
+                .with_system(connect::update_matchbox_socket),
+        );
+    app
+}
+
+fn run_both(peers: &mut [App; 2], frames: usize) {
+    for _ in 0..frames {
+        for app in peers.iter_mut() {
+            app.update();
+        }
+    }
+}
+
+#[test]
+fn matching_peers_start_a_session() {
+    let network = LoopbackNetwork::default();
+    let mut peers = [
+        peer_app(&network, Ruleset::default()),
+        peer_app(&network, Ruleset::default()),
+    ];
+    run_both(&mut peers, 10);
+
+    for app in peers.iter() {
+        assert_eq!(*app.world.resource::<State<AppState>>().current(), AppState::RoundOnline);
+        assert!(app.world.contains_resource::<SessionType<GGRSConfig>>());
+        assert!(matches!(
+            app.world.resource::<LevelSelection>(),
+            LevelSelection::Identifier(level) if level == "Level_0"
+        ));
+    }
+}
+
+#[test]
+fn peers_with_other_rules_stay_on_the_connect_screen() {
+    let network = LoopbackNetwork::default();
+    let mut peers = [
+        peer_app(&network, Ruleset::default()),
+        peer_app(
+            &network,
+            Ruleset {
+                bots: 1,
+                ..default()
+            },
+        ),
+    ];
+    run_both(&mut peers, 10);
+
+    for app in peers.iter() {
+        assert_eq!(*app.world.resource::<State<AppState>>().current(), AppState::MenuConnect);
+        assert!(!app.world.contains_resource::<SessionType<GGRSConfig>>());
+    }
+}